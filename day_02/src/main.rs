@@ -1,3 +1,5 @@
+use intcode::symbolic::{self, SymbolicMachine};
+
 fn main() {
     do_main("inputs/day_02.txt");
 }
@@ -11,22 +13,19 @@ fn do_main(filename: &str) {
     println!("Position 0 contains: {}", result[0]);
     assert_eq!(result[0], 3101878);
 
-    let original_opcodes = intcode::parse_opcodes(&input);
-    let mut needle = None;
-
-    'a: for noun in 0..=99 {
-        for verb in 0..=99 {
-            let mut opcodes = original_opcodes.clone();
-            opcodes[1] = noun;
-            opcodes[2] = verb;
-            if intcode::run(opcodes)[0] == 19690720 {
-                needle = Some((noun, verb));
-                break 'a;
-            }
-        }
-    }
+    // memory 0 ends up linear in the noun and verb, so solve for them rather than trying all
+    // 10,000 pairs
+    let mut machine = SymbolicMachine::new(&intcode::parse_opcodes(&input));
+    machine.set_symbol(1, "noun");
+    machine.set_symbol(2, "verb");
+    let bounds = [("noun", 0..=99), ("verb", 0..=99)];
+    let solution = symbolic::explore(machine, 10_000, 1)
+        .paths
+        .iter()
+        .find_map(|path| path.solve(0, 19690720, &bounds).ok())
+        .expect("no satisfactory inputs found");
 
-    let (noun, verb) = needle.expect("no satisfactory inputs found");
+    let (noun, verb) = (solution["noun"], solution["verb"]);
     println!("Necessary input is: {}", 100 * noun + verb);
     assert_eq!((noun, verb), (84, 44));
 }
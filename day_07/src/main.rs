@@ -1,3 +1,5 @@
+use intcode::network::{Network, Topology};
+
 fn main() {
     do_main("inputs/day_07.txt");
 }
@@ -15,16 +17,21 @@ fn do_main(path: &str) {
     assert_eq!(max_output, 3745599);
 }
 
-fn run_thrusters(program: &[isize], phase_settings: &[isize]) -> isize {
-    let mut signal = 0;
-
-    for &phase in phase_settings {
-        let (_, output) = intcode::run_with_io(program.into(), vec![phase, signal].into());
-        assert!(output.len() == 1);
-        signal = output[0]
-    }
+fn run_amplifiers(program: &[isize], phase_settings: &[isize], topology: Topology) -> isize {
+    let phases: Vec<&[isize]> = phase_settings.iter().map(std::slice::from_ref).collect();
+    let mut network = Network::from_program(program, phases.len(), &phases, topology);
+    network.push_input(0, 0);
+    network
+        .run()
+        .unwrap_or_else(|e| panic!("amplifiers failed: {}", e));
+    *network
+        .take_output()
+        .last()
+        .expect("never received any output")
+}
 
-    signal
+fn run_thrusters(program: &[isize], phase_settings: &[isize]) -> isize {
+    run_amplifiers(program, phase_settings, Topology::Pipeline)
 }
 
 fn find_max(program: &[isize]) -> isize {
@@ -42,60 +49,7 @@ fn find_max(program: &[isize]) -> isize {
 }
 
 fn run_thrusters_loop(program: &[isize], phase_settings: &[isize]) -> isize {
-    use futures::sink::SinkExt;
-    use futures::stream::StreamExt;
-    use intcode::Status;
-
-    let mut thrusters = Vec::new();
-    for &setting in phase_settings {
-        let (mut sender, receiver) = futures::channel::mpsc::channel::<isize>(2);
-        sender
-            .try_send(setting)
-            .expect("could not send phase setting");
-        thrusters.push((
-            sender,
-            intcode::stream_with_io(program.into(), Box::new(receiver)),
-        ))
-    }
-    thrusters[0]
-        .0
-        .try_send(0)
-        .expect("could not start thruster 0");
-
-    let f = async {
-        let mut read_thruster = 0;
-        let mut write_thruster = 1;
-        let mut finished = 0;
-        let mut last_output = None;
-
-        loop {
-            read_thruster %= thrusters.len();
-            write_thruster %= thrusters.len();
-
-            let data = thrusters[read_thruster].1.next().await;
-
-            match data.expect("didn't get data") {
-                Status::Output(o) => {
-                    thrusters[write_thruster]
-                        .0
-                        .send(o)
-                        .await
-                        .expect("could not send to the next thruster");
-                    last_output = Some(o);
-                }
-                Status::Terminated(_) => finished += 1,
-            }
-
-            if finished == thrusters.len() {
-                break last_output.expect("never received any output");
-            }
-
-            read_thruster += 1;
-            write_thruster += 1;
-        }
-    };
-
-    futures::executor::block_on(f)
+    run_amplifiers(program, phase_settings, Topology::Ring)
 }
 
 fn find_max_loop(program: &[isize]) -> isize {
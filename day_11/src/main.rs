@@ -1,4 +1,3 @@
-use futures::stream::StreamExt;
 use std::collections::HashMap;
 
 fn main() {
@@ -16,12 +15,12 @@ fn do_main(path: &str) {
 }
 
 fn count_painted_panels(program: &[isize]) -> usize {
-    let panels = futures::executor::block_on(paint_panels(program, 0));
+    let panels = paint_panels(program, 0);
     panels.len()
 }
 
 fn print_painted_panels(program: &[isize]) {
-    let panels = futures::executor::block_on(paint_panels(program, 1));
+    let panels = paint_panels(program, 1);
     let min_x = panels
         .keys()
         .map(|&(x, _)| x)
@@ -44,41 +43,34 @@ fn print_painted_panels(program: &[isize]) {
     }
 }
 
-async fn paint_panels(
-    program: &[isize],
-    first_panel_color: isize,
-) -> HashMap<(isize, isize), isize> {
-    let (mut tx, rx) = futures::channel::mpsc::channel::<isize>(1);
+fn paint_panels(program: &[isize], first_panel_color: isize) -> HashMap<(isize, isize), isize> {
     let (mut x, mut y) = (0, 0);
     let (mut dx, mut dy) = (0, -1); // originally pointed *up*
     let mut panels = HashMap::<(isize, isize), isize>::new();
-    let mut intcode = intcode::stream_with_io(program.iter().cloned().collect(), Box::new(rx));
+    let mut robot = intcode::Machine::new(program.to_vec());
 
     panels.insert((0, 0), first_panel_color);
 
     loop {
         let this_color = *panels.get(&(x, y)).unwrap_or(&0);
-        tx.try_send(this_color)
-            .expect("too much queued in the mpsc channel");
-        let paint_this = intcode.next().await;
-        let direction = intcode.next().await;
-
-        let paint_this =
-            match paint_this.expect("intcode interpreter stopped before signaling termination") {
-                intcode::Status::Terminated(_) => break,
-                intcode::Status::Output(x) => x,
-            };
+        robot.push_input(this_color);
+
+        let paint_this = match next_output(&mut robot) {
+            Some(x) => x,
+            None => break,
+        };
+        let direction = next_output(&mut robot).expect("intcode program did not yield enough data");
 
         panels.insert((x, y), paint_this);
 
-        let (new_dx, new_dy) = match direction.expect("intcode program did not yield enough data") {
-            intcode::Status::Output(0) => {
+        let (new_dx, new_dy) = match direction {
+            0 => {
                 // turn left:
                 // [  0 1    * ( dx
                 //   -1 0 ]      dy )
                 (dy, -dx)
             }
-            intcode::Status::Output(1) => {
+            1 => {
                 // turn right:
                 // [ 0 -1    * ( dx
                 //   1  0 ]      dy )
@@ -96,6 +88,16 @@ async fn paint_panels(
     panels
 }
 
+// Returns None once the robot has halted.
+fn next_output(robot: &mut intcode::Machine) -> Option<isize> {
+    match robot.run_until_io() {
+        Ok(intcode::State::Output(x)) => Some(x),
+        Ok(intcode::State::Halted) => None,
+        Ok(state) => panic!("robot stopped before producing output: {:?}", state),
+        Err(e) => panic!("intcode program failed: {}", e),
+    }
+}
+
 #[cfg(test)]
 mod test {
     #[test]
@@ -1,8 +1,6 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::sync::Arc;
 
-use futures::stream::StreamExt;
+use intcode::io::{InputSource, OutputSink};
 
 fn main() {
     do_main("inputs/day_13.txt");
@@ -12,21 +10,21 @@ fn do_main(path: &str) {
     let program =
         intcode::parse_opcodes(&std::fs::read_to_string(path).expect("could not read input"));
 
-    let block_tiles = futures::executor::block_on(count_block_tiles(program.clone()));
+    let block_tiles = count_block_tiles(program.clone());
     println!("Block tiles: {}", block_tiles);
     assert_eq!(block_tiles, 309);
 
-    let score = futures::executor::block_on(get_score(program));
+    let score = get_score(program);
     println!("Score: {}", score);
     assert_eq!(score, 15410);
 }
 
-async fn count_block_tiles(program: Vec<isize>) -> usize {
-    let tiles = run_game(program, |_| panic!("This program should not take input")).await;
+fn count_block_tiles(program: Vec<isize>) -> usize {
+    let tiles = run_game(program, |_| panic!("This program should not take input"));
     tiles.iter().filter(|(_, &v)| v == 2 /* block */).count()
 }
 
-async fn get_score(mut program: Vec<isize>) -> isize {
+fn get_score(mut program: Vec<isize>) -> isize {
     // the problem statement says that the first memory cell should be '2' to play for free.
     program[0] = 2;
 
@@ -53,56 +51,52 @@ async fn get_score(mut program: Vec<isize>) -> isize {
         } else {
             0
         }
-    })
-    .await;
+    });
     tiles
         .get(&(-1, 0))
         .expect("the score was not written by the intcode program")
         .clone()
 }
 
-async fn run_game<F: FnMut(&HashMap<(isize, isize), isize>) -> isize + Unpin + 'static>(
-    program: Vec<isize>,
-    mut read_input: F,
-) -> HashMap<(isize, isize), isize> {
-    let tiles = Arc::new(RefCell::new(HashMap::new()));
-    let tiles_for_input = tiles.clone();
-    let mut intcode = intcode::stream_with_io(
-        program,
-        Box::new(futures::stream::poll_fn(move |_ctx| {
-            futures::task::Poll::Ready(Some(read_input(&tiles_for_input.borrow())))
-        })),
-    );
-
-    loop {
-        let x = intcode.next().await;
-        // check the first opcode to see if it terminated
-        let x = match x.expect("intcode interpreter ended unexpectedly") {
-            intcode::Status::Terminated(_) => break,
-            intcode::Status::Output(x) => x,
-        };
-
-        // and if it didn't terminate, assume that the other two to at least produce *something*
-        let y = intcode.next().await;
-        let tile = intcode.next().await;
-
-        let y = match y.expect("intcode interpreter ended unexpectedly") {
-            intcode::Status::Terminated(_) => break,
-            intcode::Status::Output(y) => y,
-        };
-        let tile = match tile.expect("intcode interpreter ended unexpectedly") {
-            intcode::Status::Terminated(_) => break,
-            intcode::Status::Output(tile) => tile,
-        };
+// The game's display, which also drives its joystick.  Every tile is drawn by a triple of
+// outputs: x, y, and the tile kind.
+struct Screen<F> {
+    tiles: HashMap<(isize, isize), isize>,
+    pending: Vec<isize>,
+    joystick: F,
+}
 
-        tiles.borrow_mut().insert((x, y), tile);
+impl<F: FnMut(&HashMap<(isize, isize), isize>) -> isize> InputSource for Screen<F> {
+    fn next_input(&mut self) -> Option<isize> {
+        Some((self.joystick)(&self.tiles))
     }
+}
 
-    std::mem::drop(intcode);
+impl<F> OutputSink for Screen<F> {
+    fn output(&mut self, value: isize) {
+        self.pending.push(value);
+        if self.pending.len() == 3 {
+            self.tiles
+                .insert((self.pending[0], self.pending[1]), self.pending[2]);
+            self.pending.clear();
+        }
+    }
+}
 
-    Arc::try_unwrap(tiles)
-        .expect("the intcode interpreter should no longer reference tiles")
-        .into_inner()
+fn run_game<F: FnMut(&HashMap<(isize, isize), isize>) -> isize>(
+    program: Vec<isize>,
+    read_input: F,
+) -> HashMap<(isize, isize), isize> {
+    let mut screen = Screen {
+        tiles: HashMap::new(),
+        pending: Vec::with_capacity(3),
+        joystick: read_input,
+    };
+    intcode::Machine::new(program)
+        .run_with_device(&mut screen)
+        .unwrap_or_else(|e| panic!("intcode program failed: {}", e));
+
+    screen.tiles
 }
 
 #[cfg(test)]
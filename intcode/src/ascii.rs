@@ -0,0 +1,130 @@
+// Wraps a Machine that talks in ASCII, so that callers can deal in lines of text.  Output that is
+// not ASCII (anything negative or above 127, like a puzzle's final answer) comes through as its
+// own event rather than being mangled into the text.
+
+use crate::{Error, Machine, State};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    // without its newline
+    Line(String),
+    Value(isize),
+}
+
+pub struct AsciiMachine {
+    machine: Machine,
+}
+
+pub fn encode(text: &str) -> Vec<isize> {
+    text.chars().map(|c| c as isize).collect()
+}
+
+// Splits output into lines and non-ASCII values, in the order they were output.  Text that is not
+// followed by a newline (a prompt, say) still ends up as a Line: at the end of the output, or just
+// before a value.
+pub fn decode(output: &[isize]) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut line = String::new();
+    for &value in output {
+        match value {
+            10 => events.push(Event::Line(std::mem::take(&mut line))),
+            0..=127 => line.push(value as u8 as char),
+            _ => {
+                if !line.is_empty() {
+                    events.push(Event::Line(std::mem::take(&mut line)));
+                }
+                events.push(Event::Value(value));
+            }
+        }
+    }
+    if !line.is_empty() {
+        events.push(Event::Line(line));
+    }
+    events
+}
+
+impl AsciiMachine {
+    pub fn new(machine: Machine) -> AsciiMachine {
+        AsciiMachine { machine }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    pub fn into_inner(self) -> Machine {
+        self.machine
+    }
+
+    pub fn send(&mut self, text: &str) {
+        self.machine.extend_input(encode(text));
+    }
+
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.machine.push_input('\n' as isize);
+    }
+
+    // Runs until the program halts or wants more input, and returns what it said along the way.
+    pub fn run(&mut self) -> Result<(State, Vec<Event>), Error> {
+        let state = self.machine.run_until_blocked()?;
+        Ok((state, decode(&self.machine.take_output())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsciiMachine, Event};
+    use crate::{Machine, State};
+
+    #[test]
+    fn lines() {
+        // greets, then echoes everything back until it reads a "." and prints 1000
+        let program = crate::asm::assemble(
+            r#"
+                    arb #greeting
+            hello:  jz rb+0, #loop
+                    out rb+0
+                    arb #1
+                    jz #0, #hello
+            loop:   in [c]
+                    eq [c], #46, [t]
+                    jnz [t], #done
+                    out [c]
+                    jz #0, #loop
+            done:   out #1000
+                    hlt
+            c:      .data 0
+            t:      .data 0
+            greeting: .string "Hi!\nName? \0"
+            "#,
+        )
+        .unwrap();
+        let mut machine = AsciiMachine::new(Machine::new(program));
+        assert_eq!(
+            machine.run().unwrap(),
+            (
+                State::NeedsInput,
+                vec![Event::Line("Hi!".into()), Event::Line("Name? ".into())]
+            )
+        );
+
+        machine.send_line("Bob");
+        machine.send("again.");
+        assert_eq!(
+            machine.run().unwrap(),
+            (
+                State::Halted,
+                vec![
+                    Event::Line("Bob".into()),
+                    Event::Line("again".into()),
+                    Event::Value(1000)
+                ]
+            )
+        );
+    }
+}
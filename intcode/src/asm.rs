@@ -0,0 +1,354 @@
+// Assembles the mnemonic syntax that the disassembler prints back into an intcode program.
+//
+//     start:  in [value]              ; comments run to the end of the line
+//             jz [value], #done
+//             out rb-1
+//     done:   hlt
+//     value:  .data 0
+//     text:   .string "hi\n"
+//
+// Operands are `[x]` for position mode, `#x` for immediate mode and `rb+x` for relative mode,
+// where x is a number, a label, or a label plus or minus a number.  A leading address like `12:`,
+// as printed in a disassembly listing, is ignored.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::instruction::{Instruction, Mode, Opcode, Operand};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Debug)]
+enum Expr {
+    Number(isize),
+    Label(String, isize),
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction(Opcode, Vec<(Mode, Expr)>),
+    Data(Vec<Expr>),
+    String(Vec<isize>),
+}
+
+impl Item {
+    fn size(&self) -> usize {
+        match self {
+            Item::Instruction(opcode, _) => 1 + opcode.arity(),
+            Item::Data(values) => values.len(),
+            Item::String(chars) => chars.len(),
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<isize>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+
+    for (idx, line) in source.lines().enumerate() {
+        let line_number = idx + 1;
+        let error = |message: String| AsmError {
+            line: line_number,
+            message,
+        };
+
+        let mut rest = strip_comment(line).trim();
+        while let Some((label, remainder)) = split_label(rest) {
+            if !label.chars().all(|c| c.is_ascii_digit()) {
+                check_identifier(label).map_err(error)?;
+                if labels.insert(label.to_string(), address).is_some() {
+                    return Err(error(format!("label {} defined more than once", label)));
+                }
+            }
+            rest = remainder.trim_start();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let item = parse_item(rest).map_err(error)?;
+        address += item.size();
+        items.push((line_number, item));
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line_number, item) in items {
+        let resolve = |expr: &Expr| {
+            resolve(expr, &labels).map_err(|message| AsmError {
+                line: line_number,
+                message,
+            })
+        };
+        match item {
+            Item::Instruction(opcode, operands) => {
+                let mut resolved = Vec::with_capacity(operands.len());
+                for (mode, expr) in &operands {
+                    resolved.push(Operand {
+                        mode: *mode,
+                        value: resolve(expr)?,
+                    });
+                }
+                program.extend(Instruction::new(opcode, &resolved).encode());
+            }
+            Item::Data(values) => {
+                for expr in &values {
+                    program.push(resolve(expr)?);
+                }
+            }
+            Item::String(chars) => program.extend(chars),
+        }
+    }
+
+    Ok(program)
+}
+
+fn strip_comment(line: &str) -> &str {
+    // a ';' inside a .string literal does not start a comment
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..idx],
+            _ => (),
+        }
+    }
+    line
+}
+
+// Splits "name: rest" into ("name", " rest"), if the line starts with a label.
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let label = &line[..colon];
+    if label.is_empty() || label.contains(|c: char| c.is_whitespace() || c == '"') {
+        return None;
+    }
+    Some((label, &line[colon + 1..]))
+}
+
+fn check_identifier(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid label {:?}", name))
+    }
+}
+
+fn parse_item(text: &str) -> Result<Item, String> {
+    let (word, rest) = match text.find(char::is_whitespace) {
+        Some(idx) => (&text[..idx], text[idx..].trim()),
+        None => (text, ""),
+    };
+
+    match word {
+        ".data" => Ok(Item::Data(
+            split_operands(rest)
+                .into_iter()
+                .map(parse_expr)
+                .collect::<Result<_, _>>()?,
+        )),
+        ".string" => Ok(Item::String(parse_string(rest)?)),
+        _ => {
+            let opcode = Opcode::from_mnemonic(word)
+                .ok_or_else(|| format!("unknown mnemonic {:?}", word))?;
+            let operands = split_operands(rest);
+            if operands.len() != opcode.arity() {
+                return Err(format!(
+                    "{} takes {} operands, found {}",
+                    word,
+                    opcode.arity(),
+                    operands.len()
+                ));
+            }
+
+            let mut parsed = Vec::with_capacity(operands.len());
+            for (idx, operand) in operands.into_iter().enumerate() {
+                let (mode, expr) = parse_operand(operand)?;
+                if mode == Mode::Immediate && opcode.writes(idx) {
+                    return Err(format!(
+                        "operand {} of {} cannot be immediate",
+                        idx + 1,
+                        word
+                    ));
+                }
+                parsed.push((mode, expr));
+            }
+            Ok(Item::Instruction(opcode, parsed))
+        }
+    }
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        vec![]
+    } else {
+        text.split(',').map(str::trim).collect()
+    }
+}
+
+fn parse_operand(text: &str) -> Result<(Mode, Expr), String> {
+    if text.starts_with('[') && text.ends_with(']') {
+        Ok((Mode::Position, parse_expr(text[1..text.len() - 1].trim())?))
+    } else if let Some(rest) = text.strip_prefix('#') {
+        Ok((Mode::Immediate, parse_expr(rest)?))
+    } else if let Some(rest) = text.strip_prefix("rb") {
+        let rest = rest.trim();
+        if rest.is_empty() {
+            Ok((Mode::Relative, Expr::Number(0)))
+        } else if let Some(offset) = rest.strip_prefix('+') {
+            Ok((Mode::Relative, parse_expr(offset)?))
+        } else if rest.starts_with('-') {
+            Ok((Mode::Relative, parse_expr(rest)?))
+        } else {
+            Err(format!("invalid relative operand {:?}", text))
+        }
+    } else {
+        Err(format!("invalid operand {:?}", text))
+    }
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let text = text.trim();
+    if text.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+        let number = text.trim_start_matches('+').replace(' ', "");
+        return number
+            .parse()
+            .map(Expr::Number)
+            .map_err(|_| format!("invalid number {:?}", text));
+    }
+
+    let (label, offset) = match text.rfind(['+', '-']) {
+        Some(idx) => {
+            let offset = text[idx..].trim_start_matches('+').replace(' ', "");
+            let offset = offset
+                .parse()
+                .map_err(|_| format!("invalid offset in {:?}", text))?;
+            (text[..idx].trim(), offset)
+        }
+        None => (text, 0),
+    };
+    check_identifier(label)?;
+    Ok(Expr::Label(label.to_string(), offset))
+}
+
+fn parse_string(text: &str) -> Result<Vec<isize>, String> {
+    if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+        return Err(format!("invalid string literal {}", text));
+    }
+
+    let mut result = Vec::new();
+    let mut chars = text[1..text.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some('\\') => '\\',
+                Some('"') => '"',
+                x => return Err(format!("invalid escape sequence \\{:?}", x)),
+            }
+        } else {
+            c
+        };
+        result.push(c as isize);
+    }
+    Ok(result)
+}
+
+fn resolve(expr: &Expr, labels: &HashMap<String, usize>) -> Result<isize, String> {
+    match expr {
+        Expr::Number(x) => Ok(*x),
+        Expr::Label(name, offset) => labels
+            .get(name)
+            .map(|&address| address as isize + offset)
+            .ok_or_else(|| format!("undefined label {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+
+    #[test]
+    fn labels_and_modes() {
+        let program = assemble(
+            "
+            ; output 0 if the input was zero or 1 if the input was non-zero
+            start:  in [value]
+                    jz [value], #done
+                    out #1
+                    hlt
+            done:   out rb+0
+                    hlt
+            value:  .data -1
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![3, 11, 1006, 11, 8, 104, 1, 99, 204, 0, 99, -1]
+        );
+        assert_eq!(
+            crate::run_with_io(program.clone(), vec![0].into()).1,
+            vec![3]
+        );
+        assert_eq!(crate::run_with_io(program, vec![5].into()).1, vec![1]);
+    }
+
+    #[test]
+    fn data_and_strings() {
+        assert_eq!(
+            assemble(
+                "a: .data 1, a+2, b-1\narb rb+b\nb: .string \"hi; \\\"there\\\"\\n\" ; comment"
+            )
+            .unwrap(),
+            vec![1, 2, 4, 209, 5, 104, 105, 59, 32, 34, 116, 104, 101, 114, 101, 34, 10]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(assemble("add [1], [2]").unwrap_err().line, 1);
+        assert_eq!(assemble("\nadd [1], [2], #3").unwrap_err().line, 2);
+        assert_eq!(
+            assemble("jz [0], #nowhere").unwrap_err().message,
+            "undefined label nowhere"
+        );
+        assert!(assemble("a: hlt\na: hlt").is_err());
+        assert!(assemble("frob #1").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        for day in &["05", "09", "11", "13"] {
+            let program = crate::parse_opcodes(
+                &std::fs::read_to_string(format!("../inputs/day_{}.txt", day)).unwrap(),
+            );
+            let listing = crate::disasm::listing(&program);
+            assert_eq!(assemble(&listing).unwrap(), program, "day {}", day);
+        }
+    }
+}
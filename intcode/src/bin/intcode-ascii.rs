@@ -0,0 +1,40 @@
+// Runs an ASCII intcode program interactively on stdin and stdout: intcode-ascii <program>
+//
+// Output values that are not ASCII are printed on a line of their own, in brackets.
+use std::io::{BufRead, Write};
+
+use intcode::ascii::{AsciiMachine, Event};
+use intcode::{Machine, State};
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: intcode-ascii <program>");
+    let program =
+        intcode::parse_opcodes(&std::fs::read_to_string(path).expect("could not read program"));
+    let mut machine = AsciiMachine::new(Machine::new(program));
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let (state, events) = machine.run().unwrap_or_else(|e| {
+            eprintln!("fault: {}", e);
+            std::process::exit(1);
+        });
+        for event in events {
+            match event {
+                Event::Line(line) => println!("{}", line),
+                Event::Value(x) => println!("[{}]", x),
+            }
+        }
+        std::io::stdout().flush().expect("could not write output");
+
+        if state == State::Halted {
+            break;
+        }
+        match lines.next() {
+            Some(line) => machine.send_line(&line.expect("could not read input")),
+            None => break,
+        }
+    }
+}
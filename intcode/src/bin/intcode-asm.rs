@@ -0,0 +1,15 @@
+// Assembles mnemonic source into a comma-separated intcode program: intcode-asm <source>
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: intcode-asm <source>");
+    let source = std::fs::read_to_string(path).expect("could not read source");
+
+    match intcode::asm::assemble(&source) {
+        Ok(program) => println!("{}", intcode::format_opcodes(&program)),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+// Prints the control-flow graph of an intcode program in Graphviz format:
+//
+//     intcode-cfg <program> | dot -Tsvg > cfg.svg
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: intcode-cfg <program>");
+    let program =
+        intcode::parse_opcodes(&std::fs::read_to_string(path).expect("could not read program"));
+
+    print!("{}", intcode::cfg::build(&program).to_dot());
+}
@@ -0,0 +1,41 @@
+// Runs a program once for each set of inputs and prints an annotated listing showing what each
+// run executed:
+//
+//     intcode-coverage <program> [input,...] [input,...] ...
+//
+// e.g. `intcode-coverage inputs/day_05.txt 1 5` compares the two parts of day 5.
+use intcode::coverage::{self, Coverage};
+use intcode::{Machine, State};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let path = args
+        .first()
+        .expect("usage: intcode-coverage <program> [input,...] ...");
+    let program =
+        intcode::parse_opcodes(&std::fs::read_to_string(path).expect("could not read program"));
+
+    let mut inputs: Vec<Vec<isize>> = args[1..]
+        .iter()
+        .map(|x| intcode::parse_opcodes(x))
+        .collect();
+    if inputs.is_empty() {
+        inputs.push(Vec::new());
+    }
+
+    let mut runs = Vec::new();
+    for (i, input) in inputs.into_iter().enumerate() {
+        let mut machine = Machine::new(program.clone());
+        machine.extend_input(input);
+        let mut coverage = Coverage::new();
+        match machine.run_until_blocked_with(&mut coverage) {
+            Ok(State::Halted) => (),
+            Ok(_) => eprintln!("run {}: program is waiting for more input", i + 1),
+            Err(e) => eprintln!("run {}: fault: {}", i + 1, e),
+        }
+        runs.push(coverage);
+    }
+
+    let runs: Vec<&Coverage> = runs.iter().collect();
+    print!("{}", coverage::compare(&program, &runs));
+}
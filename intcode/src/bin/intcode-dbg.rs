@@ -0,0 +1,37 @@
+// An interactive, gdb-like debugger for intcode programs: intcode-dbg [program]
+use std::io::{BufRead, Write};
+
+fn main() {
+    let mut debugger = intcode::debugger::Debugger::new();
+    if let Some(path) = std::env::args().nth(1) {
+        report(debugger.command(&format!("load {}", path)));
+    }
+
+    let stdin = std::io::stdin();
+    loop {
+        print!("(intcode) ");
+        std::io::stdout().flush().expect("could not write prompt");
+
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .expect("could not read command")
+            == 0
+        {
+            break;
+        }
+        match line.trim() {
+            "quit" | "q" => break,
+            command => report(debugger.command(command)),
+        }
+    }
+}
+
+fn report(result: Result<String, String>) {
+    match result {
+        Ok(text) if text.is_empty() => (),
+        Ok(text) => println!("{}", text),
+        Err(e) => println!("error: {}", e),
+    }
+}
@@ -0,0 +1,10 @@
+// Prints a listing of an intcode program: intcode-disasm <program>
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: intcode-disasm <program>");
+    let program =
+        intcode::parse_opcodes(&std::fs::read_to_string(path).expect("could not read program"));
+
+    print!("{}", intcode::disasm::listing(&program));
+}
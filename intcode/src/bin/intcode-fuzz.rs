@@ -0,0 +1,55 @@
+// Runs random programs through every backend and compares them with the interpreter:
+//
+//     intcode-fuzz [seeds] [--from <seed>] [--save <dir>]
+//
+// Each disagreement is minimized, printed and, with --save, written out as a test case (the
+// tests replay intcode/tests/fuzz).
+use intcode::fuzz::{self, BACKENDS};
+
+const USAGE: &str = "usage: intcode-fuzz [seeds] [--from <seed>] [--save <dir>]";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut seeds = 10_000;
+    let mut from = 0;
+    let mut save = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = args.next().and_then(|x| x.parse().ok()).expect(USAGE),
+            "--save" => save = Some(args.next().expect(USAGE)),
+            _ => seeds = arg.parse().expect(USAGE),
+        }
+    }
+
+    let mut failures = 0;
+    let mut skipped = 0;
+    for seed in from..from + seeds {
+        let case = fuzz::generate(seed);
+        if fuzz::reference(&case).is_none() {
+            skipped += 1;
+            continue;
+        }
+        if fuzz::check(&case, BACKENDS).is_ok() {
+            continue;
+        }
+        failures += 1;
+        let case = fuzz::minimize(case, BACKENDS);
+        println!(
+            "seed {}: {}",
+            seed,
+            fuzz::check(&case, BACKENDS).unwrap_err()
+        );
+        print!("{}", case.to_text());
+        if let Some(dir) = &save {
+            let path = fuzz::save(&case, dir).expect("could not save test case");
+            println!("saved as {}", path.display());
+        }
+    }
+    println!(
+        "{} programs, {} failed, {} ran too long to compare",
+        seeds, failures, skipped
+    );
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
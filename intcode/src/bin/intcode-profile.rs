@@ -0,0 +1,47 @@
+// Runs a program and reports where it spent its time:
+//
+//     intcode-profile <program> [input,...] [--csv counts.csv]
+use intcode::profile::Profile;
+use intcode::{Machine, State};
+
+const USAGE: &str = "usage: intcode-profile <program> [input,...] [--csv <path>]";
+
+// How many addresses the report lists.
+const TOP: usize = 20;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut positional = Vec::new();
+    let mut csv = None;
+    while let Some(arg) = args.next() {
+        if arg == "--csv" {
+            csv = Some(args.next().expect(USAGE));
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let path = positional.first().expect(USAGE);
+    let program =
+        intcode::parse_opcodes(&std::fs::read_to_string(path).expect("could not read program"));
+    let mut machine = Machine::new(program);
+    if let Some(input) = positional.get(1) {
+        machine.extend_input(intcode::parse_opcodes(input));
+    }
+
+    let mut profile = Profile::new();
+    match machine.run_until_blocked_with(&mut profile) {
+        Ok(State::Halted) => (),
+        Ok(_) => eprintln!("program is waiting for more input"),
+        Err(e) => eprintln!("fault: {}", e),
+    }
+    let output = machine.take_output();
+    if !output.is_empty() {
+        println!("output: {}\n", intcode::format_opcodes(&output));
+    }
+    print!("{}", profile.report(machine.memory(), TOP));
+
+    if let Some(csv) = csv {
+        profile.write_csv(&csv).expect("could not write CSV");
+    }
+}
@@ -0,0 +1,156 @@
+// Runs any intcode program to completion:
+//
+//     intcode-run <program> [input,...] [--input-file <path>] [--patch <address>=<value>,...]
+//                 [--dump-memory <path>] [--format lines|csv|ascii] [--self-modifying]
+//
+// Input values can be given as arguments and in a file, whitespace- or comma-separated; a path of
+// "-" means stdin.  Patches are applied before the program starts, like day 2's noun and verb.
+// The final memory is written to --dump-memory ("-" for stdout) once the program halts.
+// --self-modifying reports any writes to code, and which addresses were code and which data, on
+// stderr.
+use std::io::Read;
+
+use intcode::ascii::Event;
+use intcode::selfmod::CodeMap;
+use intcode::{Machine, State};
+
+const USAGE: &str = "usage: intcode-run <program> [input,...] [--input-file <path>] \
+                     [--patch <address>=<value>,...] [--dump-memory <path>] \
+                     [--format lines|csv|ascii] [--self-modifying]";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Lines,
+    Csv,
+    Ascii,
+}
+
+fn read(path: &str) -> String {
+    let mut contents = String::new();
+    if path == "-" {
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .expect("could not read stdin");
+    } else {
+        contents = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("could not read {}: {}", path, e));
+    }
+    contents
+}
+
+fn parse_values(input: &str) -> Vec<isize> {
+    input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse()
+                .unwrap_or_else(|_| panic!("{} is not an integer", x))
+        })
+        .collect()
+}
+
+fn parse_patches(patches: &str) -> Vec<(usize, isize)> {
+    patches
+        .split(',')
+        .map(|patch| {
+            let mut parts = patch.splitn(2, '=');
+            let address = parts.next().and_then(|x| x.trim().parse().ok());
+            let value = parts.next().and_then(|x| x.trim().parse().ok());
+            match (address, value) {
+                (Some(address), Some(value)) => (address, value),
+                _ => panic!("bad patch {:?}; expected <address>=<value>", patch),
+            }
+        })
+        .collect()
+}
+
+fn print_output(output: &[isize], format: Format) {
+    match format {
+        Format::Lines => {
+            for x in output {
+                println!("{}", x);
+            }
+        }
+        Format::Csv if !output.is_empty() => println!("{}", intcode::format_opcodes(output)),
+        Format::Csv => (),
+        Format::Ascii => {
+            for event in intcode::ascii::decode(output) {
+                match event {
+                    Event::Line(line) => println!("{}", line),
+                    Event::Value(x) => println!("[{}]", x),
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut program = None;
+    let mut input = Vec::new();
+    let mut patches = Vec::new();
+    let mut dump = None;
+    let mut format = Format::Lines;
+    let mut code_map = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input-file" => input.extend(parse_values(&read(&args.next().expect(USAGE)))),
+            "--patch" => patches.extend(parse_patches(&args.next().expect(USAGE))),
+            "--dump-memory" => dump = Some(args.next().expect(USAGE)),
+            "--format" => {
+                format = match args.next().expect(USAGE).as_str() {
+                    "lines" => Format::Lines,
+                    "csv" => Format::Csv,
+                    "ascii" => Format::Ascii,
+                    x => panic!("unknown format {}; expected lines, csv or ascii", x),
+                }
+            }
+            "--self-modifying" => code_map = Some(CodeMap::new()),
+            x if x.starts_with("--") => panic!("unknown option {}\n{}", x, USAGE),
+            _ if program.is_none() => program = Some(arg),
+            _ => input.extend(parse_values(&arg)),
+        }
+    }
+
+    let program = intcode::parse_opcodes(&read(&program.expect(USAGE)));
+    let mut machine = Machine::new(program);
+    for (address, value) in patches {
+        machine.write(address, value);
+    }
+    machine.extend_input(input);
+
+    let result = match &mut code_map {
+        Some(code_map) => machine.run_until_blocked_with(code_map),
+        None => machine.run_until_blocked(),
+    };
+    print_output(&machine.take_output(), format);
+    if let Some(code_map) = code_map {
+        eprint!("{}", code_map.report());
+    }
+    match result {
+        Ok(State::Halted) => (),
+        Ok(_) => {
+            eprintln!("program is waiting for more input");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("fault: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(dump) = dump {
+        let memory = match machine.try_into_memory() {
+            Ok(memory) => intcode::format_opcodes(&memory) + "\n",
+            Err(e) => {
+                eprintln!("could not dump memory: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if dump == "-" {
+            print!("{}", memory);
+        } else {
+            std::fs::write(&dump, memory).expect("could not write memory");
+        }
+    }
+}
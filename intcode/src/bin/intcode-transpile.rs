@@ -0,0 +1,23 @@
+// Compiles an intcode program to native source: intcode-transpile [--rust | --c] <program>
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (language, path) = match args.as_slice() {
+        [path] => ("--rust", path),
+        [language, path] => (language.as_str(), path),
+        _ => panic!("usage: intcode-transpile [--rust | --c] <program>"),
+    };
+    let program =
+        intcode::parse_opcodes(&std::fs::read_to_string(path).expect("could not read program"));
+
+    match language {
+        "--rust" => print!("{}", intcode::transpile::to_rust(&program)),
+        "--c" => match intcode::transpile::to_c(&program) {
+            Ok(c) => print!("{}", c),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        x => panic!("unknown language {}; expected --rust or --c", x),
+    }
+}
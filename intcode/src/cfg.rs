@@ -0,0 +1,338 @@
+// Splits a program into basic blocks by following control flow from address 0.
+//
+// Only jnz/jz with an immediate target can be followed statically.  A jump through memory or the
+// relative base marks its block as unresolved, and anything only reachable that way is left out.
+// The graph is of the program as loaded; code that rewrites itself may end up elsewhere.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::instruction::{Instruction, Mode, Opcode};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    // the branch of a jnz/jz that jumps
+    Taken,
+    // the branch of a jnz/jz that does not
+    NotTaken,
+    // into the next block, because that block is also a jump target
+    Fallthrough,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<(usize, Instruction)>,
+    pub successors: Vec<Edge>,
+    // ends in a jump whose target is not known until run time
+    pub unresolved: bool,
+}
+
+impl Block {
+    // One past the last word of the block.
+    pub fn end(&self) -> usize {
+        match self.instructions.last() {
+            Some((address, i)) => address + i.size(),
+            None => self.start,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+}
+
+pub fn build(program: &[isize]) -> Cfg {
+    // First find every reachable instruction, and every address that starts a block.
+    let mut instructions = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut pending = vec![0];
+    leaders.insert(0);
+    while let Some(mut address) = pending.pop() {
+        while !instructions.contains_key(&address) {
+            let instruction = match Instruction::decode(program, address) {
+                Some(i) => i,
+                None => break,
+            };
+            instructions.insert(address, instruction);
+            match exits(address, &instruction) {
+                Some((edges, _)) => {
+                    for edge in edges {
+                        if leaders.insert(edge.target) {
+                            pending.push(edge.target);
+                        }
+                    }
+                    break;
+                }
+                None => address += instruction.size(),
+            }
+        }
+    }
+
+    // Then chop the instructions up at the leaders.
+    let mut cfg = Cfg::default();
+    for &start in &leaders {
+        if !instructions.contains_key(&start) {
+            continue;
+        }
+        let mut block = Block {
+            start,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+            unresolved: false,
+        };
+        let mut address = start;
+        while let Some(&instruction) = instructions.get(&address) {
+            block.instructions.push((address, instruction));
+            if let Some((edges, unresolved)) = exits(address, &instruction) {
+                block.successors = edges;
+                block.unresolved = unresolved;
+                break;
+            }
+            address += instruction.size();
+            if leaders.contains(&address) {
+                block.successors.push(Edge {
+                    target: address,
+                    kind: EdgeKind::Fallthrough,
+                });
+                break;
+            }
+        }
+        // a jump to somewhere that does not decode is as good as not knowing where it goes
+        let count = block.successors.len();
+        block
+            .successors
+            .retain(|e| instructions.contains_key(&e.target));
+        block.unresolved |= block.successors.len() < count;
+        cfg.blocks.insert(start, block);
+    }
+    cfg
+}
+
+// Where control can go after a block-ending instruction, and whether that is all of them, or
+// None if the instruction just falls through to the next one.
+fn exits(address: usize, instruction: &Instruction) -> Option<(Vec<Edge>, bool)> {
+    let taken = match instruction.opcode {
+        Opcode::Jnz => |x: isize| x != 0,
+        Opcode::Jz => |x: isize| x == 0,
+        Opcode::Hlt => return Some((vec![], false)),
+        _ => return None,
+    };
+    let (condition, target) = (instruction.operands()[0], instruction.operands()[1]);
+
+    let mut edges = Vec::new();
+    let mut unresolved = false;
+    // a constant condition only ever goes one way
+    let may_take = condition.mode != Mode::Immediate || taken(condition.value);
+    let may_skip = condition.mode != Mode::Immediate || !taken(condition.value);
+    if may_take {
+        if target.mode == Mode::Immediate && target.value >= 0 {
+            edges.push(Edge {
+                target: target.value as usize,
+                kind: EdgeKind::Taken,
+            });
+        } else {
+            unresolved = true;
+        }
+    }
+    if may_skip {
+        edges.push(Edge {
+            target: address + instruction.size(),
+            kind: EdgeKind::NotTaken,
+        });
+    }
+    Some((edges, unresolved))
+}
+
+impl Cfg {
+    // The block containing `address`, if it is part of a reachable instruction.
+    pub fn block_at(&self, address: usize) -> Option<&Block> {
+        let (_, block) = self.blocks.range(..=address).next_back()?;
+        block
+            .instructions
+            .iter()
+            .any(|&(a, i)| a <= address && address < a + i.size())
+            .then_some(block)
+    }
+
+    // The immediate post-dominator of each block: the nearest block that every path from it to
+    // the end of the program goes through.  That is where the two sides of a branch meet again.
+    // Blocks that only meet at the end, including any with an unresolved jump on the way, are
+    // left out.
+    pub fn post_dominators(&self) -> BTreeMap<usize, usize> {
+        // Iterate to a fixed point, starting from "everything post-dominates everything", except
+        // for blocks that can leave the graph.  None stands for the whole set.
+        let mut sets: BTreeMap<usize, Option<BTreeSet<usize>>> = self
+            .blocks
+            .values()
+            .map(|b| {
+                let exits = b.unresolved || b.successors.is_empty();
+                (b.start, if exits { Some(BTreeSet::new()) } else { None })
+            })
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in self.blocks.values().rev() {
+                let mut set: Option<BTreeSet<usize>> = if block.unresolved {
+                    Some(BTreeSet::new())
+                } else {
+                    None
+                };
+                for edge in &block.successors {
+                    let mut successor = match &sets[&edge.target] {
+                        Some(x) => x.clone(),
+                        None => continue,
+                    };
+                    successor.insert(edge.target);
+                    set = Some(match set {
+                        Some(set) => set.intersection(&successor).cloned().collect(),
+                        None => successor,
+                    });
+                }
+                if block.successors.is_empty() {
+                    set = Some(BTreeSet::new());
+                }
+                if set.is_some() && set != sets[&block.start] {
+                    sets.insert(block.start, set);
+                    changed = true;
+                }
+            }
+        }
+
+        // the immediate one is the strict post-dominator that all the others post-dominate
+        let mut result = BTreeMap::new();
+        for (&start, set) in &sets {
+            let set = match set {
+                Some(set) => set,
+                None => continue,
+            };
+            let closest = set
+                .iter()
+                .max_by_key(|p| sets[p].as_ref().map_or(usize::MAX, |s| s.len()));
+            if let Some(&closest) = closest {
+                result.insert(start, closest);
+            }
+        }
+        result
+    }
+
+    // Renders the graph for Graphviz, e.g. `dot -Tsvg`.  Unresolved jumps point at a "?" node.
+    pub fn to_dot(&self) -> String {
+        let mut dot = "digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n".to_string();
+        let mut any_unresolved = false;
+        for block in self.blocks.values() {
+            let label: String = block
+                .instructions
+                .iter()
+                .map(|(address, i)| format!("{}: {}\\l", address, i))
+                .collect();
+            dot += &format!("    b{} [label=\"{}\"];\n", block.start, label);
+            for edge in &block.successors {
+                let attributes = match edge.kind {
+                    EdgeKind::Taken => "label=\"taken\"",
+                    EdgeKind::NotTaken => "label=\"not taken\", style=dashed",
+                    EdgeKind::Fallthrough => "style=dashed",
+                };
+                dot += &format!(
+                    "    b{} -> b{} [{}];\n",
+                    block.start, edge.target, attributes
+                );
+            }
+            if block.unresolved {
+                any_unresolved = true;
+                dot += &format!("    b{} -> unresolved [style=dotted];\n", block.start);
+            }
+        }
+        if any_unresolved {
+            dot += "    unresolved [shape=diamond, label=\"?\"];\n";
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Edge, EdgeKind};
+
+    #[test]
+    fn blocks() {
+        let program = crate::asm::assemble(
+            "
+            loop:   in [n]
+                    jz [n], #done
+                    out [n]
+                    jnz #1, #loop
+            done:   add [n], #1, [n]
+            after:  jz [n], [n]
+                    hlt
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = super::build(&program);
+        let starts: Vec<usize> = cfg.blocks.keys().cloned().collect();
+        assert_eq!(starts, vec![0, 5, 10, 17]);
+
+        assert_eq!(
+            cfg.blocks[&0].successors,
+            vec![
+                Edge {
+                    target: 10,
+                    kind: EdgeKind::Taken
+                },
+                Edge {
+                    target: 5,
+                    kind: EdgeKind::NotTaken
+                }
+            ]
+        );
+        assert_eq!(
+            cfg.blocks[&5].successors,
+            vec![Edge {
+                target: 0,
+                kind: EdgeKind::Taken
+            }]
+        );
+        assert_eq!(cfg.blocks[&10].end(), 17);
+        assert!(cfg.blocks[&10].unresolved);
+        assert!(cfg.blocks[&17].successors.is_empty());
+        assert!(!cfg.blocks[&17].unresolved);
+
+        assert_eq!(cfg.block_at(15).map(|b| b.start), Some(10));
+        // the loop is left at done, but done's block ends in a jump that cannot be followed
+        let post_dominators = cfg.post_dominators();
+        assert_eq!(post_dominators.get(&0), Some(&10));
+        assert_eq!(post_dominators.get(&5), Some(&0));
+        assert_eq!(post_dominators.get(&10), None);
+        assert_eq!(cfg.block_at(18), None);
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b0 [label=\"0: in [18]\\l2: jz [18], #10\\l\"];\n"));
+        assert!(dot.contains("    b0 -> b10 [label=\"taken\"];\n"));
+        assert!(dot.contains("    b10 -> unresolved [style=dotted];\n"));
+    }
+
+    #[test]
+    fn day_11() {
+        let program =
+            crate::parse_opcodes(&std::fs::read_to_string("../inputs/day_11.txt").unwrap());
+        let cfg = super::build(&program);
+        assert!(cfg.blocks.contains_key(&0));
+        for block in cfg.blocks.values() {
+            for edge in &block.successors {
+                assert!(cfg.blocks.contains_key(&edge.target));
+            }
+        }
+        // subroutines return through the stack, so some jumps cannot be followed
+        assert!(cfg.blocks.values().any(|b| b.unresolved));
+    }
+}
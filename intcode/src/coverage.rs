@@ -0,0 +1,324 @@
+// Records which instructions a program executed, and which way each conditional jump went, so
+// that the parts no test input reaches can be found:
+//
+//     let mut coverage = Coverage::new();
+//     machine.run_until_blocked_with(&mut coverage)?;
+//     print!("{}", coverage.report(&program));
+//
+// The same Coverage can watch several runs to add them up, or compare() can put separate runs
+// side by side.  In the annotated listing, the first column is how many times an instruction ran,
+// with ##### for one that never did, as gcov does.
+//
+// Instructions are listed as they were when they ran, which for self-modifying code is not
+// necessarily how they appear in the program; those are marked as modified.
+
+use std::collections::BTreeMap;
+
+use crate::disasm::{self, Contents, Line};
+use crate::instruction::{Instruction, Mode};
+use crate::Observer;
+
+// How often a conditional jump jumped, and how often it did not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Branch {
+    pub jumped: u64,
+    pub fell_through: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    counts: BTreeMap<usize, u64>,
+    branches: BTreeMap<usize, Branch>,
+    // the words of each instruction, as it was the last time it ran
+    code: BTreeMap<usize, Vec<isize>>,
+    current: Option<(usize, Vec<isize>)>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    // How many times the instruction at each address retired.
+    pub fn executed(&self) -> &BTreeMap<usize, u64> {
+        &self.counts
+    }
+
+    pub fn branches(&self) -> &BTreeMap<usize, Branch> {
+        &self.branches
+    }
+
+    // Adds another run's coverage to this one.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&address, &count) in &other.counts {
+            *self.counts.entry(address).or_insert(0) += count;
+        }
+        for (&address, branch) in &other.branches {
+            let total = self.branches.entry(address).or_default();
+            total.jumped += branch.jumped;
+            total.fell_through += branch.fell_through;
+        }
+        for (address, words) in &other.code {
+            self.code.entry(*address).or_insert_with(|| words.clone());
+        }
+    }
+
+    // An annotated listing of `program`, which should be the program as it was loaded.
+    pub fn report(&self, program: &[isize]) -> String {
+        compare(program, &[self])
+    }
+}
+
+impl Observer for Coverage {
+    fn begin(&mut self, ip: usize, &instruction: &isize) {
+        self.current = Some((ip, vec![instruction]));
+    }
+
+    fn operand(&mut self, _mode: Mode, &parameter: &isize, _value: &isize) {
+        if let Some((_, words)) = &mut self.current {
+            words.push(parameter);
+        }
+    }
+
+    fn retire(&mut self, next_ip: usize) {
+        if let Some((ip, words)) = self.current.take() {
+            *self.counts.entry(ip).or_insert(0) += 1;
+            if let 5 | 6 = words[0] % 100 {
+                let branch = self.branches.entry(ip).or_default();
+                if next_ip == ip + words.len() {
+                    branch.fell_through += 1;
+                } else {
+                    branch.jumped += 1;
+                }
+            }
+            self.code.insert(ip, words);
+        }
+    }
+}
+
+// An annotated listing of `program` with a column of counts for each run.  Where runs executed
+// different instructions at the same address, each version gets its own line.
+pub fn compare(program: &[isize], runs: &[&Coverage]) -> String {
+    let lines = listing(program, runs);
+    // whether a run's counts belong on a line
+    let ran = |run: &Coverage, line: &Listed| match &line.ran {
+        Some(words) => run.code.get(&line.line.address) == Some(words),
+        None => true,
+    };
+    let is_instruction = |line: &&Listed| matches!(line.line.contents, Contents::Instruction(_));
+    let is_jump = |line: &&Listed| match &line.line.contents {
+        Contents::Instruction(i) => matches!(i.encode()[0] % 100, 5 | 6),
+        _ => false,
+    };
+    let instructions = lines.iter().filter(is_instruction).count();
+    let jumps = lines.iter().filter(is_jump).count();
+
+    let mut report = String::new();
+    for (i, run) in runs.iter().enumerate() {
+        let executed = lines
+            .iter()
+            .filter(|line| ran(run, line) && run.counts.contains_key(&line.line.address))
+            .count();
+        let both_ways = lines
+            .iter()
+            .filter(is_jump)
+            .filter(|line| {
+                ran(run, line)
+                    && run
+                        .branches
+                        .get(&line.line.address)
+                        .is_some_and(|b| b.jumped > 0 && b.fell_through > 0)
+            })
+            .count();
+        report += &format!(
+            "run {}: {} of {} instructions executed, {} of {} jumps went both ways\n",
+            i + 1,
+            executed,
+            instructions,
+            both_ways,
+            jumps
+        );
+    }
+    report += "\n";
+
+    for listed in &lines {
+        let address = listed.line.address;
+        let mut branches = Vec::new();
+        for run in runs {
+            let count = run.counts.get(&address).filter(|_| ran(run, listed));
+            let column = match (count, &listed.line.contents) {
+                (Some(count), _) => count.to_string(),
+                (None, Contents::Instruction(_)) => "#####".to_string(),
+                (None, Contents::Data(_)) => String::new(),
+            };
+            report += &format!("{:>8} ", column);
+            if let Some(b) = run.branches.get(&address).filter(|_| ran(run, listed)) {
+                branches.push(format!(
+                    "jumped {}, fell through {}",
+                    b.jumped, b.fell_through
+                ));
+            }
+        }
+        report += &format!("| {}", listed.line);
+        if listed.modified {
+            report += " (modified)";
+        }
+        if !branches.is_empty() {
+            report += &format!("  [{}]", branches.join(" / "));
+        }
+        report += "\n";
+    }
+    report
+}
+
+struct Listed {
+    line: Line,
+    // the words that ran, if the line shows an instruction that did
+    ran: Option<Vec<isize>>,
+    // whether that differs from what the program has at this address
+    modified: bool,
+}
+
+// Sweeps `program` like disasm::lines, except that anything the runs executed is shown as it was
+// when it ran, and the sweep always resynchronizes on it.
+fn listing(program: &[isize], runs: &[&Coverage]) -> Vec<Listed> {
+    let executed = |address: usize| runs.iter().any(|run| run.code.contains_key(&address));
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let mut versions: Vec<&Vec<isize>> = Vec::new();
+        for run in runs {
+            if let Some(words) = run.code.get(&address) {
+                if !versions.contains(&words) {
+                    versions.push(words);
+                }
+            }
+        }
+
+        let mut next = None;
+        for words in versions {
+            if let Some(instruction) = Instruction::decode(words, 0) {
+                let line = Line {
+                    address,
+                    contents: Contents::Instruction(instruction),
+                };
+                let end = address + line.size();
+                next = Some(next.map_or(end, |next: usize| next.min(end)));
+                lines.push(Listed {
+                    line,
+                    modified: program.get(address..address + words.len()) != Some(&words[..]),
+                    ran: Some(words.clone()),
+                });
+            }
+        }
+
+        address = match next {
+            Some(next) => next,
+            None => {
+                // stop before the next thing that ran, so it gets a line of its own
+                let end = (address + 1..program.len())
+                    .find(|&a| executed(a))
+                    .unwrap_or(program.len());
+                let line = disasm::lines_at(&program[address..end], address)
+                    .next()
+                    .unwrap();
+                let next = address + line.size();
+                lines.push(Listed {
+                    line,
+                    ran: None,
+                    modified: false,
+                });
+                next
+            }
+        };
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, Branch, Coverage};
+    use crate::Machine;
+
+    fn run(program: &[isize], input: isize) -> Coverage {
+        let mut machine = Machine::new(program.to_vec());
+        machine.push_input(input);
+        let mut coverage = Coverage::new();
+        machine.run_until_blocked_with(&mut coverage).unwrap();
+        coverage
+    }
+
+    #[test]
+    fn report() {
+        // outputs 1 if its input is negative, 0 otherwise
+        let program = crate::asm::assemble(
+            "
+                    in [x]
+                    lt [x], #0, [x]
+                    jz [x], #zero
+                    out #1
+                    hlt
+            zero:   out #0
+                    hlt
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+
+        let coverage = run(&program, 5);
+        assert_eq!(coverage.executed().len(), 5);
+        assert_eq!(
+            coverage.branches()[&6],
+            Branch {
+                jumped: 1,
+                fell_through: 0
+            }
+        );
+        assert_eq!(
+            coverage.report(&program),
+            "run 1: 5 of 7 instructions executed, 0 of 1 jumps went both ways
+
+       1 |      0: in [15]                         ; 3,15
+       1 |      2: lt [15], #0, [15]               ; 1007,15,0,15
+       1 |      6: jz [15], #12                    ; 1006,15,12  [jumped 1, fell through 0]
+   ##### |      9: out #1                          ; 104,1
+   ##### |     11: hlt                             ; 99
+       1 |     12: out #0                          ; 104,0
+       1 |     14: hlt                             ; 99
+         |     15: .data 0
+"
+        );
+
+        let mut both = run(&program, -5);
+        both.merge(&coverage);
+        assert!(both
+            .report(&program)
+            .starts_with("run 1: 7 of 7 instructions executed, 1 of 1 jumps went both ways\n"));
+    }
+
+    #[test]
+    fn day_05() {
+        let program = crate::parse_opcodes(
+            &std::fs::read_to_string("../inputs/day_05.txt").expect("could not read input"),
+        );
+        let first = run(&program, 1);
+        let second = run(&program, 5);
+        let report = compare(&program, &[&first, &second]);
+
+        assert!(report.starts_with(
+            "run 1: 62 of 185 instructions executed, 0 of 45 jumps went both ways\n\
+             run 2: 104 of 185 instructions executed, 0 of 45 jumps went both ways\n"
+        ));
+        // the input is added to an instruction, which makes it an add for part 1 and a jump over
+        // part 1's tests for part 2
+        assert!(report.contains(
+            "       1    ##### |      6: add #1, #238, [225]             ; 1101,1,238,225 (modified)\n"
+        ));
+        assert!(report.contains(
+            "   #####        1 |      6: jnz #1, #238                    ; 1105,1,238 (modified)  \
+             [jumped 1, fell through 0]\n"
+        ));
+        assert!(!first.executed().contains_key(&238));
+        assert!(!second.executed().contains_key(&10));
+    }
+}
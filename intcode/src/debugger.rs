@@ -0,0 +1,454 @@
+// The command interpreter behind the intcode-dbg binary.  Each command returns the text to show
+// the user, or an error message.
+
+use std::collections::BTreeSet;
+
+use crate::disasm;
+use crate::history::History;
+use crate::instruction::Instruction;
+use crate::{Machine, Memory, Observer, State};
+
+const HELP: &str = "\
+load <path>        load a program (or a snapshot)
+break <addr>       stop before executing the instruction at addr
+watch <addr>       stop after anything writes to addr
+delete <addr>      remove any breakpoint or watchpoint at addr
+step [n]           execute n instructions (default 1)
+next               run until the instruction after this one
+continue           run until a breakpoint, watchpoint, input, halt or fault
+back [n]           undo n instructions (default 1)
+back-to <addr>     undo instructions until the one at addr is next again
+x/N <addr>         show N words of memory starting at addr
+info regs          show ip, relative base and pending input
+info break         list breakpoints and watchpoints
+input <values>     queue input for the program
+disas [addr]       disassemble around the current ip (or from addr)
+quit               leave the debugger";
+
+// How many lines `disas` shows.
+const DISAS_LINES: usize = 10;
+
+#[derive(Default)]
+pub struct Debugger {
+    machine: Option<Machine>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    history: History,
+}
+
+struct Watch<'a> {
+    watchpoints: &'a BTreeSet<usize>,
+    hit: Option<(usize, isize, isize)>,
+}
+
+impl<'a> Observer for Watch<'a> {
+    fn write(&mut self, address: usize, &old: &isize, &new: &isize) {
+        if self.hit.is_none() && self.watchpoints.contains(&address) {
+            self.hit = Some((address, old, new));
+        }
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn with_machine(machine: Machine) -> Debugger {
+        Debugger {
+            machine: Some(machine),
+            ..Debugger::default()
+        }
+    }
+
+    pub fn machine(&self) -> Option<&Machine> {
+        self.machine.as_ref()
+    }
+
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(x) => x,
+            None => return Ok(String::new()),
+        };
+        let args: Vec<&str> = words.collect();
+
+        match (command, args.as_slice()) {
+            ("help", []) => Ok(HELP.to_string()),
+            ("load", [path]) => self.load(path),
+            ("break", [addr]) | ("b", [addr]) => {
+                let addr = parse_address(addr)?;
+                self.breakpoints.insert(addr);
+                Ok(format!("breakpoint at {}", addr))
+            }
+            ("watch", [addr]) => {
+                let addr = parse_address(addr)?;
+                self.watchpoints.insert(addr);
+                Ok(format!("watchpoint at {}", addr))
+            }
+            ("delete", [addr]) => {
+                let addr = parse_address(addr)?;
+                let removed = self.breakpoints.remove(&addr) | self.watchpoints.remove(&addr);
+                if removed {
+                    Ok(format!("deleted {}", addr))
+                } else {
+                    Err(format!("nothing set at {}", addr))
+                }
+            }
+            ("step", []) | ("s", []) => self.resume(1, None),
+            ("step", [n]) | ("s", [n]) => {
+                let n = n.parse().map_err(|_| format!("invalid count {}", n))?;
+                self.resume(n, None)
+            }
+            ("next", []) | ("n", []) => {
+                let machine = self.loaded()?;
+                let ip = machine.ip();
+                let size = Instruction::decode(&window(machine, ip, 4), 0)
+                    .map(|i| i.size())
+                    .unwrap_or(1);
+                self.resume(usize::MAX, Some(ip + size))
+            }
+            ("continue", []) | ("c", []) => self.resume(usize::MAX, None),
+            ("back", []) => self.back(1, None),
+            ("back", [n]) => {
+                let n = n.parse().map_err(|_| format!("invalid count {}", n))?;
+                self.back(n, None)
+            }
+            ("back-to", [addr]) => {
+                let addr = parse_address(addr)?;
+                self.back(usize::MAX, Some(addr))
+            }
+            ("info", ["regs"]) => {
+                let machine = self.loaded()?;
+                let input: Vec<isize> = machine.pending_input().iter().cloned().collect();
+                Ok(format!(
+                    "ip {}\nrelative_base {}\ninput [{}]{}",
+                    machine.ip(),
+                    machine.relative_base(),
+                    crate::format_opcodes(&input),
+                    if machine.is_halted() { "\nhalted" } else { "" }
+                ))
+            }
+            ("info", ["break"]) => {
+                let mut lines = Vec::new();
+                lines.extend(self.breakpoints.iter().map(|a| format!("break {}", a)));
+                lines.extend(self.watchpoints.iter().map(|a| format!("watch {}", a)));
+                Ok(lines.join("\n"))
+            }
+            ("input", values) if !values.is_empty() => {
+                let mut parsed = Vec::new();
+                for value in values.iter().flat_map(|v| v.split(',')) {
+                    if !value.is_empty() {
+                        parsed.push(
+                            value
+                                .parse()
+                                .map_err(|_| format!("invalid input {}", value))?,
+                        );
+                    }
+                }
+                let machine = self.loaded_mut()?;
+                machine.extend_input(parsed);
+                Ok(format!(
+                    "{} value(s) pending",
+                    machine.pending_input().len()
+                ))
+            }
+            ("disas", []) => {
+                let ip = self.loaded()?.ip();
+                Ok(self.disassemble(ip))
+            }
+            ("disas", [addr]) => {
+                let addr = parse_address(addr)?;
+                self.loaded()?;
+                Ok(self.disassemble(addr))
+            }
+            (x, [addr]) if x == "x" || x.starts_with("x/") => {
+                let count = match x.strip_prefix("x/") {
+                    Some(n) => n.parse().map_err(|_| format!("invalid count {}", n))?,
+                    None => 1,
+                };
+                let addr = parse_address(addr)?;
+                self.examine(addr, count)
+            }
+            _ => Err(format!("unknown command {:?}; try \"help\"", line.trim())),
+        }
+    }
+
+    fn loaded(&self) -> Result<&Machine, String> {
+        self.machine
+            .as_ref()
+            .ok_or_else(|| "no program loaded".to_string())
+    }
+
+    fn loaded_mut(&mut self) -> Result<&mut Machine, String> {
+        self.machine
+            .as_mut()
+            .ok_or_else(|| "no program loaded".to_string())
+    }
+
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let machine = if text.starts_with("intcode-snapshot") {
+            Machine::from_snapshot(&text).map_err(|e| e.to_string())?
+        } else {
+            let program = text
+                .trim()
+                .split(',')
+                .map(|x| x.trim().parse())
+                .collect::<Result<Vec<isize>, _>>()
+                .map_err(|_| format!("{}: not an intcode program", path))?;
+            Machine::new(program)
+        };
+        let words = machine.memory().extent();
+        self.machine = Some(machine);
+        self.history.clear();
+        Ok(format!("loaded {} words from {}", words, path))
+    }
+
+    // Executes at most `limit` instructions, stopping early for anything interesting, including
+    // reaching `until`.
+    fn resume(&mut self, limit: usize, until: Option<usize>) -> Result<String, String> {
+        let breakpoints = &self.breakpoints;
+        let watchpoints = &self.watchpoints;
+        let history = &mut self.history;
+        let machine = self
+            .machine
+            .as_mut()
+            .ok_or_else(|| "no program loaded".to_string())?;
+
+        let mut report = Vec::new();
+        for _ in 0..limit {
+            let mut watch = Watch {
+                watchpoints,
+                hit: None,
+            };
+            let ip = machine.ip();
+            match machine.step_with(&mut (&mut watch, &mut *history)) {
+                Ok(State::Running) => (),
+                Ok(State::Output(x)) => report.push(format!("output: {}", x)),
+                Ok(State::NeedsInput) => {
+                    report.push("program is waiting for input".to_string());
+                    break;
+                }
+                Ok(State::Halted) => {
+                    report.push("program halted".to_string());
+                    break;
+                }
+                Err(e) => {
+                    report.push(format!("fault: {}", e));
+                    break;
+                }
+            }
+            if let Some((address, old, new)) = watch.hit {
+                report.push(format!(
+                    "watchpoint {}: {} -> {} (written by ip {})",
+                    address, old, new, ip
+                ));
+                break;
+            }
+            if until == Some(machine.ip()) {
+                break;
+            }
+            if breakpoints.contains(&machine.ip()) {
+                report.push(format!("breakpoint {}", machine.ip()));
+                break;
+            }
+        }
+
+        report.push(self.current_line());
+        Ok(report.join("\n"))
+    }
+
+    // Undoes at most `limit` instructions, stopping early at `until`.
+    fn back(&mut self, limit: usize, until: Option<usize>) -> Result<String, String> {
+        let machine = self
+            .machine
+            .as_mut()
+            .ok_or_else(|| "no program loaded".to_string())?;
+
+        let mut undone = 0;
+        while undone < limit && self.history.step_back(machine) {
+            undone += 1;
+            if until == Some(machine.ip()) {
+                break;
+            }
+        }
+
+        let mut report = Vec::new();
+        if undone < limit && until != Some(machine.ip()) {
+            report.push("no more history".to_string());
+        }
+        report.push(self.current_line());
+        Ok(report.join("\n"))
+    }
+
+    fn current_line(&self) -> String {
+        let machine = match &self.machine {
+            Some(m) => m,
+            None => return String::new(),
+        };
+        match Instruction::decode(&window(machine, machine.ip(), 4), 0) {
+            Some(i) => format!("=> {}: {}", machine.ip(), i),
+            None => format!("=> {}: .data {}", machine.ip(), machine.read(machine.ip())),
+        }
+    }
+
+    fn disassemble(&self, start: usize) -> String {
+        let machine = match &self.machine {
+            Some(m) => m,
+            None => return String::new(),
+        };
+        // enough words for DISAS_LINES of the longest instructions
+        disasm::lines_at(&window(machine, start, DISAS_LINES * 4), start)
+            .take(DISAS_LINES)
+            .map(|line| {
+                let marker = if line.address == machine.ip() {
+                    "=>"
+                } else if self.breakpoints.contains(&line.address) {
+                    " *"
+                } else {
+                    "  "
+                };
+                format!("{}{}", marker, line)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn examine(&self, start: usize, count: usize) -> Result<String, String> {
+        let machine = self.loaded()?;
+        let end = start
+            .checked_add(count)
+            .ok_or_else(|| format!("{} words from {} runs past the last address", count, start))?;
+        let mut lines = Vec::new();
+        for row in (start..end).step_by(8) {
+            let values: Vec<String> = (row..row.saturating_add(8).min(end))
+                .map(|a| machine.read(a).to_string())
+                .collect();
+            lines.push(format!("{:>6}: {}", row, values.join(" ")));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+fn window(machine: &Machine, start: usize, len: usize) -> Vec<isize> {
+    (start..start.saturating_add(len))
+        .map(|a| machine.read(a))
+        .collect()
+}
+
+fn parse_address(text: &str) -> Result<usize, String> {
+    text.parse()
+        .map_err(|_| format!("invalid address {:?}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Debugger;
+    use crate::Machine;
+
+    fn debugger() -> Debugger {
+        // doubles each input until it sees a zero
+        let program = crate::asm::assemble(
+            "
+            loop:   in [value]
+                    jz [value], #done
+                    mul [value], #2, [value]
+                    out [value]
+                    jz #0, #loop
+            done:   hlt
+            value:  .data 0
+            ",
+        )
+        .unwrap();
+        Debugger::with_machine(Machine::new(program))
+    }
+
+    #[test]
+    fn breakpoints_and_stepping() {
+        let mut debugger = debugger();
+        assert_eq!(
+            debugger.command("step").unwrap(),
+            "program is waiting for input\n=> 0: in [15]"
+        );
+        debugger.command("input 3, 0").unwrap();
+        debugger.command("break 9").unwrap();
+        assert_eq!(
+            debugger.command("continue").unwrap(),
+            "breakpoint 9\n=> 9: out [15]"
+        );
+        assert_eq!(debugger.command("x/2 14").unwrap(), "    14: 99 6");
+        assert_eq!(
+            debugger.command("next").unwrap(),
+            "output: 6\n=> 11: jz #0, #0"
+        );
+        assert_eq!(
+            debugger.command("info regs").unwrap(),
+            "ip 11\nrelative_base 0\ninput [0]"
+        );
+        assert_eq!(debugger.command("c").unwrap(), "program halted\n=> 14: hlt");
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut debugger = debugger();
+        debugger.command("input 5").unwrap();
+        debugger.command("watch 15").unwrap();
+        assert_eq!(
+            debugger.command("continue").unwrap(),
+            "watchpoint 15: 0 -> 5 (written by ip 0)\n=> 2: jz [15], #14"
+        );
+        assert_eq!(
+            debugger.command("continue").unwrap(),
+            "watchpoint 15: 5 -> 10 (written by ip 5)\n=> 9: out [15]"
+        );
+        assert_eq!(debugger.command("info break").unwrap(), "watch 15");
+        debugger.command("delete 15").unwrap();
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "output: 10\nprogram is waiting for input\n=> 0: in [15]"
+        );
+    }
+
+    #[test]
+    fn reverse() {
+        let mut debugger = debugger();
+        debugger.command("input 5, 7, 0").unwrap();
+        debugger.command("watch 15").unwrap();
+        debugger.command("continue").unwrap();
+        debugger.command("continue").unwrap();
+        assert_eq!(debugger.command("x 15").unwrap(), "    15: 10");
+
+        assert_eq!(
+            debugger.command("back").unwrap(),
+            "=> 5: mul [15], #2, [15]"
+        );
+        assert_eq!(debugger.command("x 15").unwrap(), "    15: 5");
+        assert_eq!(debugger.command("back-to 0").unwrap(), "=> 0: in [15]");
+        assert_eq!(
+            debugger.command("info regs").unwrap(),
+            "ip 0\nrelative_base 0\ninput [5,7,0]"
+        );
+        assert_eq!(
+            debugger.command("back 3").unwrap(),
+            "no more history\n=> 0: in [15]"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut debugger = Debugger::new();
+        assert!(debugger.command("step").is_err());
+        assert!(debugger.command("frobnicate").is_err());
+        assert!(debugger.command("break x").is_err());
+        assert_eq!(debugger.command("").unwrap(), "");
+
+        // the last words of memory can be shown, but not past them
+        let mut loaded = self::debugger();
+        assert!(loaded.command(&format!("x/10 {}", usize::MAX)).is_err());
+        assert_eq!(
+            loaded.command(&format!("x/1 {}", usize::MAX - 1)).unwrap(),
+            format!("{:>6}: 0", usize::MAX - 1)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+
+use crate::instruction::Instruction;
+
+// How many words of undecodable data get grouped onto one line of the listing.
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Contents {
+    Instruction(Instruction),
+    Data(Vec<isize>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub contents: Contents,
+}
+
+impl Line {
+    // The number of words of the program this line covers.
+    pub fn size(&self) -> usize {
+        match &self.contents {
+            Contents::Instruction(i) => i.size(),
+            Contents::Data(d) => d.len(),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.contents {
+            Contents::Instruction(i) => {
+                let text = format!("{:>6}: {}", self.address, i);
+                let encoded: Vec<String> = i.encode().iter().map(|x| x.to_string()).collect();
+                write!(f, "{:<40}; {}", text, encoded.join(","))
+            }
+            Contents::Data(d) => {
+                let values: Vec<String> = d.iter().map(|x| x.to_string()).collect();
+                write!(f, "{:>6}: .data {}", self.address, values.join(", "))
+            }
+        }
+    }
+}
+
+// Linearly sweeps `program` starting at `start`, decoding instructions wherever possible.
+pub struct Lines<'a> {
+    program: &'a [isize],
+    origin: usize,
+    idx: usize,
+}
+
+pub fn lines(program: &[isize], start: usize) -> Lines<'_> {
+    Lines {
+        program,
+        origin: 0,
+        idx: start,
+    }
+}
+
+// Like `lines`, for a window of `words` that was copied out of memory starting at `origin`.
+pub fn lines_at(words: &[isize], origin: usize) -> Lines<'_> {
+    Lines {
+        program: words,
+        origin,
+        idx: 0,
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        if self.idx >= self.program.len() {
+            return None;
+        }
+
+        let idx = self.idx;
+        let contents = match Instruction::decode(self.program, idx) {
+            Some(i) => Contents::Instruction(i),
+            None => {
+                let mut data = vec![self.program[idx]];
+                while data.len() < DATA_PER_LINE
+                    && idx + data.len() < self.program.len()
+                    && Instruction::decode(self.program, idx + data.len()).is_none()
+                {
+                    data.push(self.program[idx + data.len()]);
+                }
+                Contents::Data(data)
+            }
+        };
+
+        let line = Line {
+            address: self.origin + idx,
+            contents,
+        };
+        self.idx += line.size();
+        Some(line)
+    }
+}
+
+pub fn listing(program: &[isize]) -> String {
+    lines(program, 0).map(|l| format!("{}\n", l)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn listing() {
+        let listing = super::listing(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(
+            listing,
+            "     0: add [9], [10], [3]              ; 1,9,10,3
+     4: mul [3], [11], [0]              ; 2,3,11,0
+     8: hlt                             ; 99
+     9: .data 30, 40, 50
+"
+        );
+    }
+
+    #[test]
+    fn modes() {
+        let lines: Vec<String> = super::lines(&[109, 1, 21101, 7, -7, 3, 11104, 0], 0)
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "     0: arb #1                          ; 109,1",
+                "     2: add #7, #-7, rb+3               ; 21101,7,-7,3",
+                "     6: .data 11104, 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn window() {
+        let lines: Vec<String> = super::lines_at(&[4, 7, 99], 1000)
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "  1000: out [7]                         ; 4,7",
+                "  1002: hlt                             ; 99",
+            ]
+        );
+    }
+}
@@ -0,0 +1,337 @@
+// A Machine that decodes each instruction once, up front, instead of on every execution.  Writes
+// throw away any decoded instruction they land on, so self-modifying programs still behave.
+//
+// It runs exactly like Machine (same states, same faults, same cycle counts) but has no observers,
+// limits or word types other than isize.  Anything it cannot decode is handed to Machine to find
+// out what the fault is.
+
+use std::collections::VecDeque;
+
+use crate::instruction::{Mode, Opcode};
+use crate::memory::AutoMemory;
+use crate::{Error, ErrorKind, Machine, Memory, State};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Decoded {
+    opcode: Opcode,
+    modes: [Mode; 3],
+    parameters: [isize; 3],
+}
+
+#[derive(Clone, Debug)]
+pub struct FastMachine {
+    memory: AutoMemory,
+    // one entry per address of the program as loaded; None where nothing valid has been decoded
+    decoded: Vec<Option<Decoded>>,
+    ip: usize,
+    relative_base: isize,
+    input: VecDeque<isize>,
+    output: VecDeque<isize>,
+    halted: bool,
+    cycles: u64,
+}
+
+impl FastMachine {
+    pub fn new(program: Vec<isize>) -> FastMachine {
+        let memory = AutoMemory::from(program);
+        let decoded = (0..memory.extent()).map(|a| decode(&memory, a)).collect();
+        FastMachine {
+            memory,
+            decoded,
+            ip: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            halted: false,
+            cycles: 0,
+        }
+    }
+
+    pub fn push_input(&mut self, value: isize) {
+        self.input.push_back(value);
+    }
+
+    pub fn extend_input<I: IntoIterator<Item = isize>>(&mut self, values: I) {
+        self.input.extend(values);
+    }
+
+    pub fn pop_output(&mut self) -> Option<isize> {
+        self.output.pop_front()
+    }
+
+    pub fn take_output(&mut self) -> Vec<isize> {
+        self.output.drain(..).collect()
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // As for Machine, this panics if memory is too large to copy out.
+    pub fn into_memory(self) -> Vec<isize> {
+        self.try_into_memory().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_into_memory(self) -> Result<Vec<isize>, Error> {
+        match self.memory.try_to_vec() {
+            Some(memory) => Ok(memory),
+            None => Err(self.fault(ErrorKind::MemoryTooLarge(self.memory.extent()))),
+        }
+    }
+
+    pub fn read(&self, address: usize) -> isize {
+        self.memory.read(address)
+    }
+
+    pub fn write(&mut self, address: usize, value: isize) {
+        self.memory.write(address, value);
+        // an instruction is at most four words long, so only these can have included `address`
+        let end = (address + 1).min(self.decoded.len());
+        for entry in &mut self.decoded[address.saturating_sub(3).min(end)..end] {
+            *entry = None;
+        }
+    }
+
+    pub fn fault(&self, kind: ErrorKind) -> Error {
+        Error {
+            ip: self.ip,
+            instruction: self.read(self.ip),
+            kind,
+        }
+    }
+
+    pub fn step(&mut self) -> Result<State, Error> {
+        if self.halted {
+            return Ok(State::Halted);
+        }
+
+        let instruction = match self.decoded_at(self.ip) {
+            Some(i) => i,
+            None => return Err(self.interpreter_fault()),
+        };
+        let state = self.execute(instruction).map_err(|kind| self.fault(kind))?;
+        if state != State::NeedsInput {
+            self.cycles += 1;
+        }
+        Ok(state)
+    }
+
+    pub fn run_until_io(&mut self) -> Result<State, Error> {
+        loop {
+            match self.step()? {
+                State::Running => (),
+                x => return Ok(x),
+            }
+        }
+    }
+
+    pub fn run_until_blocked(&mut self) -> Result<State, Error> {
+        loop {
+            match self.run_until_io()? {
+                State::Output(x) => self.output.push_back(x),
+                x => return Ok(x),
+            }
+        }
+    }
+
+    fn decoded_at(&mut self, address: usize) -> Option<Decoded> {
+        match self.decoded.get(address) {
+            Some(Some(i)) => Some(*i),
+            Some(None) => {
+                let instruction = decode(&self.memory, address);
+                self.decoded[address] = instruction;
+                instruction
+            }
+            // beyond the original program: not worth caching
+            None => decode(&self.memory, address),
+        }
+    }
+
+    // Everything `decode` turns down is certain to fault, so let the real interpreter work out
+    // exactly how.
+    fn interpreter_fault(&self) -> Error {
+        let mut machine = Machine::with_memory(self.memory.clone());
+        machine.set_ip(self.ip);
+        machine.set_relative_base(self.relative_base);
+        machine.extend_input(self.input.iter().cloned());
+        machine
+            .step()
+            .expect_err("the fast path only gives up on instructions that fault")
+    }
+
+    fn execute(&mut self, i: Decoded) -> Result<State, ErrorKind> {
+        let state = match i.opcode {
+            Opcode::Add => {
+                let value = self.value(&i, 0)?.wrapping_add(self.value(&i, 1)?);
+                let destination = self.address(&i, 2)?;
+                self.write(destination, value);
+                self.ip += 4;
+                State::Running
+            }
+            Opcode::Mul => {
+                let value = self.value(&i, 0)?.wrapping_mul(self.value(&i, 1)?);
+                let destination = self.address(&i, 2)?;
+                self.write(destination, value);
+                self.ip += 4;
+                State::Running
+            }
+            Opcode::In => {
+                let destination = self.address(&i, 0)?;
+                let value = match self.input.pop_front() {
+                    Some(x) => x,
+                    None => return Ok(State::NeedsInput),
+                };
+                self.write(destination, value);
+                self.ip += 2;
+                State::Running
+            }
+            Opcode::Out => {
+                let value = self.value(&i, 0)?;
+                self.ip += 2;
+                State::Output(value)
+            }
+            Opcode::Jnz | Opcode::Jz => {
+                let comparison = self.value(&i, 0)?;
+                let target = self.value(&i, 1)?;
+                if (comparison != 0) == (i.opcode == Opcode::Jnz) {
+                    if target < 0 {
+                        return Err(ErrorKind::InvalidJump(target));
+                    }
+                    self.ip = target as usize;
+                } else {
+                    self.ip += 3;
+                }
+                State::Running
+            }
+            Opcode::Lt | Opcode::Eq => {
+                let (a, b) = (self.value(&i, 0)?, self.value(&i, 1)?);
+                let destination = self.address(&i, 2)?;
+                let value = if i.opcode == Opcode::Lt {
+                    a < b
+                } else {
+                    a == b
+                };
+                self.write(destination, value as isize);
+                self.ip += 4;
+                State::Running
+            }
+            Opcode::Arb => {
+                self.relative_base = self.relative_base.wrapping_add(self.value(&i, 0)?);
+                self.ip += 2;
+                State::Running
+            }
+            Opcode::Hlt => {
+                self.halted = true;
+                State::Halted
+            }
+        };
+        Ok(state)
+    }
+
+    fn value(&self, i: &Decoded, idx: usize) -> Result<isize, ErrorKind> {
+        match i.modes[idx] {
+            Mode::Immediate => Ok(i.parameters[idx]),
+            _ => Ok(self.read(self.address(i, idx)?)),
+        }
+    }
+
+    fn address(&self, i: &Decoded, idx: usize) -> Result<usize, ErrorKind> {
+        let address = match i.modes[idx] {
+            Mode::Relative => i.parameters[idx].wrapping_add(self.relative_base),
+            _ => i.parameters[idx],
+        };
+        if address < 0 {
+            return Err(ErrorKind::InvalidAddress(address));
+        }
+        Ok(address as usize)
+    }
+}
+
+// Like Instruction::decode, but as forgiving as Machine is: unused mode digits are ignored, and
+// operands past the end of memory read as zero.
+fn decode(memory: &AutoMemory, address: usize) -> Option<Decoded> {
+    let word = memory.read(address);
+    if word < 0 {
+        return None;
+    }
+    let opcode = Opcode::from_code(word % 100)?;
+
+    let mut decoded = Decoded {
+        opcode,
+        modes: [Mode::Position; 3],
+        parameters: [0; 3],
+    };
+    let mut modes = word % 100_000 / 100;
+    for idx in 0..opcode.arity() {
+        decoded.modes[idx] = Mode::from_code(modes % 10)?;
+        if decoded.modes[idx] == Mode::Immediate && opcode.writes(idx) {
+            return None;
+        }
+        decoded.parameters[idx] = memory.read(address + 1 + idx);
+        modes /= 10;
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FastMachine;
+    use crate::{Machine, State};
+
+    // Runs `program` on both machines, feeding them the same input, and checks they agree.
+    fn compare(program: Vec<isize>, input: Vec<isize>) {
+        let mut fast = FastMachine::new(program.clone());
+        let mut slow = Machine::new(program);
+        fast.extend_input(input.clone());
+        slow.extend_input(input);
+
+        assert_eq!(fast.run_until_blocked(), slow.run_until_blocked());
+        assert_eq!(fast.take_output(), slow.take_output());
+        assert_eq!((fast.ip(), fast.cycles()), (slow.ip(), slow.cycles()));
+        assert_eq!(fast.into_memory(), slow.into_memory());
+    }
+
+    #[test]
+    fn matches_interpreter() {
+        for day in &["05", "09"] {
+            let path = format!("../inputs/day_{}.txt", day);
+            let program = crate::parse_opcodes(&std::fs::read_to_string(path).unwrap());
+            for input in 1..=5 {
+                compare(program.clone(), vec![input]);
+            }
+        }
+        compare(vec![1101, 1, 2, 0, 22201, 0, 0, -1, 99], vec![]);
+        compare(vec![3, 5, 104, 1, 99, 0], vec![]);
+        compare(vec![1105, 1, -4], vec![]);
+        compare(vec![11101, 1, 2, 0, 99], vec![]);
+        compare(vec![42], vec![]);
+    }
+
+    #[test]
+    fn self_modifying() {
+        // rewrites the `mul` at 4 into an `add` before reaching it
+        let program = vec![1101, 0, 1, 4, 2, 9, 9, 9, 99, 21];
+        let mut machine = FastMachine::new(program.clone());
+        assert_eq!(machine.run_until_blocked(), Ok(State::Halted));
+        assert_eq!(machine.read(9), 42);
+        compare(program, vec![]);
+
+        // and overwrites an operand of the next instruction
+        let program = vec![1101, 0, 7, 5, 104, 1, 99];
+        let mut machine = FastMachine::new(program.clone());
+        assert_eq!(machine.run_until_io(), Ok(State::Output(7)));
+        compare(program, vec![]);
+    }
+}
@@ -0,0 +1,434 @@
+// Differential fuzzing: random programs are run through Machine, as the reference, and through
+// each alternative backend, which must give the same output, final memory and faults.
+//
+//     for seed in 0..1000 {
+//         let case = fuzz::generate(seed);
+//         if fuzz::check(&case, fuzz::BACKENDS).is_err() {
+//             fuzz::save(&fuzz::minimize(case, fuzz::BACKENDS), "tests/fuzz")?;
+//         }
+//     }
+//
+// Generated programs are well-formed to start with: every loop is counted, so it ends.  But they
+// do occasionally write over their own code or move the relative base somewhere unexpected, so
+// they can still fault or run away; those that run too long on the reference are not compared.
+//
+// The random numbers come from a small built-in generator, so a seed means the same program
+// everywhere, forever.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, ErrorKind, Limits, Machine, State};
+
+// (memory, output) from a run to completion, as try_run_with_io returns.
+pub type Run = Result<(Vec<isize>, Vec<isize>), Error>;
+
+pub type Backend = fn(Vec<isize>, VecDeque<isize>) -> Run;
+
+// The transpiler is not among them: its output has to go through a compiler before it can run, so
+// it cannot be called on a fresh program from here.  tests/transpile.rs compares it against the
+// interpreter on real programs instead.
+pub const BACKENDS: &[(&str, Backend)] = &[("fast", crate::try_run_with_io)];
+
+// Instructions the reference may run before a program is given up on.
+const MAX_CYCLES: u64 = 10_000;
+
+// Cells in a generated program's data area.
+const DATA: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+    pub program: Vec<isize>,
+    pub input: Vec<isize>,
+}
+
+impl Case {
+    // The program and the input on a line each; lines starting with # are comments.
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}\n{}\n",
+            crate::format_opcodes(&self.program),
+            crate::format_opcodes(&self.input)
+        )
+    }
+
+    pub fn from_text(text: &str) -> Option<Case> {
+        let mut lines = text.lines().filter(|l| !l.starts_with('#'));
+        let mut parse = || -> Option<Vec<isize>> {
+            lines
+                .next()?
+                .split(',')
+                .filter(|x| !x.trim().is_empty())
+                .map(|x| x.trim().parse().ok())
+                .collect()
+        };
+        Some(Case {
+            program: parse()?,
+            input: parse().unwrap_or_default(),
+        })
+    }
+}
+
+// xorshift64*
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // the state must not be zero
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    // low..=high
+    pub fn between(&mut self, low: isize, high: isize) -> isize {
+        low + self.below((high - low + 1) as usize) as isize
+    }
+
+    // true one time in n
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+}
+
+pub fn generate(seed: u64) -> Case {
+    let mut rng = Rng::new(seed);
+    let program = crate::asm::assemble(&generate_source(&mut rng))
+        .unwrap_or_else(|e| panic!("generated bad assembly for seed {}: {}", seed, e));
+    let input = (0..32).map(|_| rng.between(-100, 100)).collect();
+    Case { program, input }
+}
+
+// Assembly for a random program.  Every top-level item gets a label, t0, t1, ..., which forward
+// jumps (and the occasional self-modifying write) aim at.
+fn generate_source(rng: &mut Rng) -> String {
+    let items = 5 + rng.below(30);
+    let mut loops = 0;
+    let mut source = vec!["        arb #data".to_string()];
+    for item in 0..items {
+        let label = format!("t{}:", item);
+        let text = match rng.below(10) {
+            0 if item + 1 < items => {
+                let target = rng.between(item as isize + 1, items as isize - 1);
+                let mnemonic = if rng.one_in(2) { "jz" } else { "jnz" };
+                format!("{} {}, #t{}", mnemonic, read_operand(rng, items), target)
+            }
+            1 => {
+                // a counted loop around a few straight-line instructions
+                let counter = format!("[counters+{}]", loops);
+                loops += 1;
+                let mut lines = vec![format!("add #{}, #0, {}", rng.between(1, 5), counter)];
+                lines.push(format!("l{}:", loops));
+                for _ in 0..1 + rng.below(4) {
+                    lines.push(straight_line(rng, items));
+                }
+                lines.push(format!("add {}, #-1, {}", counter, counter));
+                lines.push(format!("jnz {}, #l{}", counter, loops));
+                lines.join("\n        ")
+            }
+            2 if rng.one_in(3) => format!("arb #{}", rng.between(-3, 3)),
+            _ => straight_line(rng, items),
+        };
+        source.push(format!("{:<8}{}", label, text));
+    }
+    source.push("        hlt".to_string());
+    source.push(format!(
+        "counters: .data {}",
+        vec!["0"; loops.max(1)].join(", ")
+    ));
+    let data: Vec<String> = (0..DATA)
+        .map(|_| rng.between(-20, 20).to_string())
+        .collect();
+    source.push(format!("data:   .data {}", data.join(", ")));
+    source.join("\n")
+}
+
+fn straight_line(rng: &mut Rng, items: usize) -> String {
+    match rng.below(6) {
+        0 => format!("in {}", write_operand(rng, items)),
+        1 => format!("out {}", read_operand(rng, items)),
+        n => format!(
+            "{} {}, {}, {}",
+            ["add", "mul", "lt", "eq"][n - 2],
+            read_operand(rng, items),
+            read_operand(rng, items),
+            write_operand(rng, items)
+        ),
+    }
+}
+
+fn read_operand(rng: &mut Rng, items: usize) -> String {
+    match rng.below(10) {
+        0..=2 => format!("#{}", rng.between(-50, 50)),
+        3..=5 => format!("[data+{}]", rng.below(DATA)),
+        6..=8 => format!("rb+{}", rng.below(DATA)),
+        _ => format!("[t{}+{}]", rng.below(items), rng.below(4)),
+    }
+}
+
+fn write_operand(rng: &mut Rng, items: usize) -> String {
+    match rng.below(25) {
+        0 => format!("[t{}+{}]", rng.below(items), rng.below(4)),
+        1..=12 => format!("[data+{}]", rng.below(DATA)),
+        _ => format!("rb+{}", rng.below(DATA)),
+    }
+}
+
+// Machine's result for a case, or None if it runs for too long to be worth comparing.
+pub fn reference(case: &Case) -> Option<Run> {
+    let mut machine = Machine::new(case.program.clone());
+    machine.extend_input(case.input.iter().cloned());
+    machine.set_limits(Limits {
+        instructions: Some(MAX_CYCLES),
+        ..Limits::default()
+    });
+    Some(match machine.run_until_blocked() {
+        Ok(State::Halted) => {
+            let output = machine.take_output();
+            machine.try_into_memory().map(|memory| (memory, output))
+        }
+        Ok(_) => Err(machine.fault(ErrorKind::InsufficientInput)),
+        Err(Error {
+            kind: ErrorKind::LimitExceeded(_),
+            ..
+        }) => return None,
+        Err(e) => Err(e),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub backend: String,
+    pub expected: Run,
+    pub actual: Run,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |result: &Run| match result {
+            Ok((memory, output)) => format!(
+                "output [{}], memory [{}]",
+                crate::format_opcodes(output),
+                crate::format_opcodes(memory)
+            ),
+            Err(e) => format!("fault: {}", e),
+        };
+        write!(
+            f,
+            "{} differs from the reference\n  expected {}\n  actual   {}",
+            self.backend,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+// Memory that only differs in how far it has been extended with zeroes is the same.
+fn normalize(result: Run) -> Run {
+    result.map(|(mut memory, output)| {
+        while memory.last() == Some(&0) {
+            memory.pop();
+        }
+        (memory, output)
+    })
+}
+
+// Compares every backend against the reference.  Cases the reference gives up on pass.
+pub fn check(case: &Case, backends: &[(&str, Backend)]) -> Result<(), Mismatch> {
+    let expected = match reference(case) {
+        Some(x) => normalize(x),
+        None => return Ok(()),
+    };
+    for (name, backend) in backends {
+        let actual = normalize(backend(
+            case.program.clone(),
+            case.input.iter().cloned().collect(),
+        ));
+        if actual != expected {
+            return Err(Mismatch {
+                backend: name.to_string(),
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Shrinks a failing case for as long as it keeps failing: drops input, cuts out runs of words,
+// and brings the remaining words closer to zero.  The result may be a quite different program,
+// but it is one that still shows a difference.
+pub fn minimize(mut case: Case, backends: &[(&str, Backend)]) -> Case {
+    let fails = |case: &Case| check(case, backends).is_err();
+    if !fails(&case) {
+        return case;
+    }
+
+    loop {
+        let before = case.clone();
+
+        while !case.input.is_empty() {
+            let mut shorter = case.clone();
+            shorter.input.pop();
+            if !fails(&shorter) {
+                break;
+            }
+            case = shorter;
+        }
+
+        let mut chunk = case.program.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start + chunk <= case.program.len() {
+                let mut smaller = case.clone();
+                smaller.program.drain(start..start + chunk);
+                if !smaller.program.is_empty() && fails(&smaller) {
+                    case = smaller;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+
+        for words in [true, false] {
+            let len = if words {
+                case.program.len()
+            } else {
+                case.input.len()
+            };
+            for i in 0..len {
+                loop {
+                    let value = if words {
+                        case.program[i]
+                    } else {
+                        case.input[i]
+                    };
+                    let simpler = match value {
+                        0 => break,
+                        x if x.unsigned_abs() <= 2 => 0,
+                        x => x / 2,
+                    };
+                    let mut smaller = case.clone();
+                    if words {
+                        smaller.program[i] = simpler;
+                    } else {
+                        smaller.input[i] = simpler;
+                    }
+                    if !fails(&smaller) {
+                        break;
+                    }
+                    case = smaller;
+                }
+            }
+        }
+
+        if case == before {
+            return case;
+        }
+    }
+}
+
+// Writes a case into `dir` as a regression test, named after its contents so that saving the
+// same case twice leaves one file.
+pub fn save<P: AsRef<Path>>(case: &Case, dir: P) -> std::io::Result<PathBuf> {
+    let text = case.to_text();
+    // FNV-1a
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    });
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.as_ref().join(format!("case-{:016x}.txt", hash));
+    std::fs::write(&path, text)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::{check, generate, minimize, reference, Case, Run, BACKENDS};
+    use crate::{Error, ErrorKind};
+
+    #[test]
+    fn generated_programs_run() {
+        let mut halted = 0;
+        for seed in 0..100 {
+            let case = generate(seed);
+            assert_eq!(generate(seed), case);
+            if let Some(Ok(_)) = reference(&case) {
+                halted += 1;
+            }
+        }
+        // most programs should get to the end, rather than fault or run away
+        assert!(halted > 50, "only {} programs halted", halted);
+    }
+
+    #[test]
+    fn text() {
+        let case = Case {
+            program: vec![3, 0, 4, 0, 99],
+            input: vec![],
+        };
+        assert_eq!(Case::from_text(&case.to_text()), Some(case.clone()));
+        assert_eq!(
+            Case::from_text("# comment\n3,0,4,0,99\n"),
+            Some(case.clone())
+        );
+    }
+
+    // A backend that gets output wrong whenever it outputs a 7.
+    fn broken(program: Vec<isize>, input: VecDeque<isize>) -> Run {
+        let (memory, mut output) = crate::try_run_with_io(program, input)?;
+        if output.contains(&7) {
+            output.push(0);
+        }
+        Ok((memory, output))
+    }
+
+    #[test]
+    fn minimizes() {
+        let backends: &[(&str, super::Backend)] = &[("broken", broken)];
+        let case = (0..)
+            .map(generate)
+            .find(|case| check(case, backends).is_err())
+            .unwrap();
+        let minimized = minimize(case.clone(), backends);
+        assert!(check(&minimized, backends).is_err());
+        assert!(minimized.program.len() < case.program.len());
+        assert!(check(&minimized, BACKENDS).is_ok());
+
+        // isize::MIN has no absolute value, but can still be shrunk
+        let case = Case {
+            program: vec![3, 0, 104, 7, 99],
+            input: vec![isize::MIN],
+        };
+        assert!(check(&minimize(case, backends), backends).is_err());
+
+        // and the reference itself is the same as the fast path for a fault
+        let case = Case {
+            program: vec![3, 0, 99],
+            input: vec![],
+        };
+        assert_eq!(
+            reference(&case),
+            Some(Err(Error {
+                ip: 0,
+                instruction: 3,
+                kind: ErrorKind::InsufficientInput
+            }))
+        );
+        assert!(check(&case, BACKENDS).is_ok());
+    }
+}
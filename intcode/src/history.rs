@@ -0,0 +1,253 @@
+// An undo log for stepping a Machine backwards.  Run the machine with a History as its observer
+// (or part of it), and each instruction that retires is recorded along with what it overwrote:
+//
+//     let mut history = History::new(10_000);
+//     machine.run_until_blocked_with(&mut history)?;
+//     history.run_back_to(&mut machine, 42);
+//
+// Rewinding restores memory, ip, the relative base and any input that was consumed, but output
+// that has already been handed to the caller stays handed out.  Only the most recent `cap`
+// instructions are kept, so that long runs stay bounded.
+
+use std::collections::VecDeque;
+
+use crate::{Machine, Memory, Observer, Word};
+
+// Enough to put back one instruction.
+#[derive(Clone, Debug)]
+struct Entry<W> {
+    ip: usize,
+    // taken from the input queue, in order
+    inputs: Vec<W>,
+    // (address, old value), in the order they were written
+    writes: Vec<(usize, W)>,
+    relative_base: Option<W>,
+}
+
+// How many instructions History::default keeps.
+pub const DEFAULT_CAP: usize = 100_000;
+
+#[derive(Clone, Debug)]
+pub struct History<W = isize> {
+    entries: VecDeque<Entry<W>>,
+    cap: usize,
+    // the instruction being executed, until it retires
+    current: Option<Entry<W>>,
+}
+
+impl<W: Word> History<W> {
+    pub fn new(cap: usize) -> History<W> {
+        History {
+            entries: VecDeque::new(),
+            cap,
+            current: None,
+        }
+    }
+
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    pub fn set_cap(&mut self, cap: usize) {
+        self.cap = cap;
+        self.trim();
+    }
+
+    // How many instructions can be undone.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.current = None;
+    }
+
+    // Undoes the last instruction the machine retired, or returns false if there is nothing left
+    // to undo.  The machine must be the one the history was recorded from.
+    pub fn step_back<M: Memory<W>>(&mut self, machine: &mut Machine<W, M>) -> bool {
+        let entry = match self.entries.pop_back() {
+            Some(x) => x,
+            None => return false,
+        };
+        for (address, old) in entry.writes.into_iter().rev() {
+            machine.write(address, old);
+        }
+        if let Some(relative_base) = entry.relative_base {
+            machine.set_relative_base(relative_base);
+        }
+        machine.unretire(entry.ip, entry.inputs);
+        true
+    }
+
+    // Steps back until the machine is about to execute the instruction at `address` again.  At
+    // least one instruction is undone, so repeating this goes further back each time.  Returns
+    // false, with everything undone, if the history runs out first.
+    pub fn run_back_to<M: Memory<W>>(
+        &mut self,
+        machine: &mut Machine<W, M>,
+        address: usize,
+    ) -> bool {
+        while self.step_back(machine) {
+            if machine.ip() == address {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn trim(&mut self) {
+        while self.entries.len() > self.cap {
+            self.entries.pop_front();
+        }
+    }
+}
+
+impl<W: Word> Default for History<W> {
+    fn default() -> History<W> {
+        History::new(DEFAULT_CAP)
+    }
+}
+
+impl<W: Word> Observer<W> for History<W> {
+    fn begin(&mut self, ip: usize, _instruction: &W) {
+        self.current = Some(Entry {
+            ip,
+            inputs: Vec::new(),
+            writes: Vec::new(),
+            relative_base: None,
+        });
+    }
+
+    fn input(&mut self, value: &W) {
+        if let Some(entry) = &mut self.current {
+            entry.inputs.push(value.clone());
+        }
+    }
+
+    fn write(&mut self, address: usize, old: &W, _new: &W) {
+        if let Some(entry) = &mut self.current {
+            entry.writes.push((address, old.clone()));
+        }
+    }
+
+    fn relative_base(&mut self, old: &W, _new: &W) {
+        if let Some(entry) = &mut self.current {
+            entry.relative_base = Some(old.clone());
+        }
+    }
+
+    fn retire(&mut self, _next_ip: usize) {
+        if let Some(entry) = self.current.take() {
+            self.entries.push_back(entry);
+            self.trim();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::opcodes::{Action, Opcodes};
+    use crate::{Machine, State};
+
+    fn program() -> Vec<isize> {
+        // sums its input into [total] until it reads a zero, then outputs the total
+        crate::asm::assemble(
+            "
+            loop:   in [x]
+                    jz [x], #done
+                    add [total], [x], [total]
+                    arb #1
+                    jz #0, #loop
+            done:   out [total]
+                    hlt
+            x:      .data 0
+            total:  .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn rewind() {
+        let mut machine = Machine::new(program());
+        machine.extend_input(vec![3, 4, 0]);
+        let mut history = History::new(100);
+        assert_eq!(
+            machine.run_until_blocked_with(&mut history),
+            Ok(State::Halted)
+        );
+        assert_eq!(machine.take_output(), vec![7]);
+        let cycles = machine.cycles();
+
+        // back to just before the last add
+        assert!(history.run_back_to(&mut machine, 5));
+        assert_eq!(machine.read(18), 3);
+        assert_eq!(machine.read(17), 4);
+        assert_eq!(machine.relative_base(), 1);
+        assert_eq!(machine.pending_input().iter().collect::<Vec<_>>(), [&0]);
+        assert!(!machine.is_halted());
+        assert_eq!(machine.cycles(), cycles - 7);
+
+        // all the way back to the start
+        assert!(!history.run_back_to(&mut machine, 1000));
+        assert_eq!(machine.ip(), 0);
+        assert_eq!(machine.cycles(), 0);
+        assert_eq!(machine.relative_base(), 0);
+        assert_eq!(machine.pending_input().len(), 3);
+        assert_eq!(machine.into_memory(), program());
+    }
+
+    #[test]
+    fn cap() {
+        let mut machine = Machine::new(program());
+        machine.extend_input(vec![3, 4, 0]);
+        let mut history = History::new(4);
+        machine.run_until_blocked_with(&mut history).unwrap();
+        assert_eq!(history.len(), 4);
+
+        for _ in 0..4 {
+            assert!(history.step_back(&mut machine));
+        }
+        assert!(!history.step_back(&mut machine));
+        // before the final in, jz, out and hlt
+        assert_eq!(machine.ip(), 0);
+        assert_eq!(machine.read(18), 7);
+    }
+
+    #[test]
+    fn custom_input() {
+        // 30 adds the next two inputs and outputs the sum
+        let mut opcodes = Opcodes::new();
+        opcodes.register(30, 0, |operands| {
+            // nothing may be taken before asking for more input
+            if operands.pending_input().len() < 2 {
+                return Ok(Action::NeedsInput);
+            }
+            let a = operands.input().unwrap();
+            let b = operands.input().unwrap();
+            Ok(Action::Output(a + b))
+        });
+        let mut machine = Machine::new(vec![30, 30, 99]);
+        machine.set_opcodes(opcodes);
+        machine.extend_input(vec![1, 2, 3, 4]);
+        let mut history = History::new(100);
+        assert_eq!(
+            machine.run_until_blocked_with(&mut history),
+            Ok(State::Halted)
+        );
+        assert_eq!(machine.take_output(), vec![3, 7]);
+
+        assert!(history.run_back_to(&mut machine, 1));
+        assert_eq!(machine.pending_input().iter().collect::<Vec<_>>(), [&3, &4]);
+        assert!(history.run_back_to(&mut machine, 0));
+        assert_eq!(machine.pending_input().len(), 4);
+        assert_eq!(machine.run_until_blocked(), Ok(State::Halted));
+        assert_eq!(machine.take_output(), vec![3, 7]);
+    }
+}
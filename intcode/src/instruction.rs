@@ -0,0 +1,261 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Add,
+    Mul,
+    In,
+    Out,
+    Jnz,
+    Jz,
+    Lt,
+    Eq,
+    Arb,
+    Hlt,
+}
+
+pub const OPCODES: [Opcode; 10] = [
+    Opcode::Add,
+    Opcode::Mul,
+    Opcode::In,
+    Opcode::Out,
+    Opcode::Jnz,
+    Opcode::Jz,
+    Opcode::Lt,
+    Opcode::Eq,
+    Opcode::Arb,
+    Opcode::Hlt,
+];
+
+impl Opcode {
+    pub fn from_code(code: isize) -> Option<Opcode> {
+        OPCODES.iter().cloned().find(|op| op.code() == code)
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        OPCODES.iter().cloned().find(|op| op.mnemonic() == mnemonic)
+    }
+
+    pub fn code(self) -> isize {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Mul => 2,
+            Opcode::In => 3,
+            Opcode::Out => 4,
+            Opcode::Jnz => 5,
+            Opcode::Jz => 6,
+            Opcode::Lt => 7,
+            Opcode::Eq => 8,
+            Opcode::Arb => 9,
+            Opcode::Hlt => 99,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "add",
+            Opcode::Mul => "mul",
+            Opcode::In => "in",
+            Opcode::Out => "out",
+            Opcode::Jnz => "jnz",
+            Opcode::Jz => "jz",
+            Opcode::Lt => "lt",
+            Opcode::Eq => "eq",
+            Opcode::Arb => "arb",
+            Opcode::Hlt => "hlt",
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => 3,
+            Opcode::Jnz | Opcode::Jz => 2,
+            Opcode::In | Opcode::Out | Opcode::Arb => 1,
+            Opcode::Hlt => 0,
+        }
+    }
+
+    // Whether the operand at (0-based) `idx` is a destination, which cannot be immediate.
+    pub fn writes(self, idx: usize) -> bool {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => idx == 2,
+            Opcode::In => idx == 0,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    pub fn from_code(code: isize) -> Option<Mode> {
+        match code {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> isize {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Operand {
+    pub mode: Mode,
+    pub value: isize,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative => write!(f, "rb{:+}", self.value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    operands: [Operand; 3],
+}
+
+impl Instruction {
+    pub fn new(opcode: Opcode, operands: &[Operand]) -> Instruction {
+        assert_eq!(operands.len(), opcode.arity(), "wrong number of operands");
+        let mut instruction = Instruction {
+            opcode,
+            operands: [Operand {
+                mode: Mode::Position,
+                value: 0,
+            }; 3],
+        };
+        instruction.operands[..operands.len()].copy_from_slice(operands);
+        instruction
+    }
+
+    // Decodes the instruction at `address`, as long as it is one that `encode` would have produced:
+    // every mode digit is valid and used, destinations are not immediate, and all of the operands
+    // are present in `memory`.
+    pub fn decode(memory: &[isize], address: usize) -> Option<Instruction> {
+        let word = *memory.get(address)?;
+        if word < 0 {
+            return None;
+        }
+        let opcode = Opcode::from_code(word % 100)?;
+
+        let mut modes = word / 100;
+        let mut operands = [Operand {
+            mode: Mode::Position,
+            value: 0,
+        }; 3];
+        for (idx, operand) in operands.iter_mut().enumerate().take(opcode.arity()) {
+            operand.mode = Mode::from_code(modes % 10)?;
+            if operand.mode == Mode::Immediate && opcode.writes(idx) {
+                return None;
+            }
+            operand.value = *memory.get(address + 1 + idx)?;
+            modes /= 10;
+        }
+        if modes != 0 {
+            return None;
+        }
+
+        Some(Instruction { opcode, operands })
+    }
+
+    pub fn operands(&self) -> &[Operand] {
+        &self.operands[..self.opcode.arity()]
+    }
+
+    // The number of words this instruction takes up in memory.
+    pub fn size(&self) -> usize {
+        1 + self.opcode.arity()
+    }
+
+    pub fn encode(&self) -> Vec<isize> {
+        let mut word = self.opcode.code();
+        let mut scale = 100;
+        for operand in self.operands() {
+            word += operand.mode.code() * scale;
+            scale *= 10;
+        }
+
+        let mut words = vec![word];
+        words.extend(self.operands().iter().map(|o| o.value));
+        words
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+        for (idx, operand) in self.operands().iter().enumerate() {
+            let separator = if idx == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, operand)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Instruction, Mode, Opcode, Operand};
+
+    #[test]
+    fn decode() {
+        let instruction = Instruction::decode(&[1002, 4, 3, 4, 33], 0).unwrap();
+        assert_eq!(instruction.opcode, Opcode::Mul);
+        assert_eq!(
+            instruction.operands(),
+            &[
+                Operand {
+                    mode: Mode::Position,
+                    value: 4
+                },
+                Operand {
+                    mode: Mode::Immediate,
+                    value: 3
+                },
+                Operand {
+                    mode: Mode::Position,
+                    value: 4
+                },
+            ]
+        );
+        assert_eq!(instruction.to_string(), "mul [4], #3, [4]");
+        assert_eq!(instruction.encode(), vec![1002, 4, 3, 4]);
+
+        assert_eq!(
+            Instruction::decode(&[204, -1], 0).unwrap().to_string(),
+            "out rb-1"
+        );
+    }
+
+    #[test]
+    fn not_instructions() {
+        // immediate destination
+        assert_eq!(Instruction::decode(&[11101, 1, 1, 1], 0), None);
+        // mode digit for a parameter that does not exist
+        assert_eq!(Instruction::decode(&[1104, 1], 0), None);
+        // invalid mode
+        assert_eq!(Instruction::decode(&[304, 1], 0), None);
+        // truncated
+        assert_eq!(Instruction::decode(&[1, 0, 0], 0), None);
+        assert_eq!(Instruction::decode(&[-1], 0), None);
+        assert_eq!(Instruction::decode(&[42], 0), None);
+    }
+}
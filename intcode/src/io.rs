@@ -0,0 +1,304 @@
+// Pluggable places for a machine's input to come from and its output to go to.
+//
+// The synchronous traits are implemented for VecDeque, closures, std channels, and (through
+// `Iter`) any iterator; output also goes into a Vec.  The asynchronous ones are implemented for
+// any Stream, futures channels, and every synchronous sink; wrap a synchronous source in
+// `Immediate` to use it asynchronously.
+//
+// Something that needs to see its own output to decide on its next input, like a game's
+// joystick watching the screen, can implement both traits on one type and be run with
+// `Machine::run_with_device`.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc as async_mpsc;
+use futures::future::poll_fn;
+use futures::Stream;
+
+use crate::{Error, Machine, State};
+
+pub trait InputSource {
+    // None if there is no input to give (yet); the machine is left waiting for it.
+    fn next_input(&mut self) -> Option<isize>;
+}
+
+pub trait OutputSink {
+    fn output(&mut self, value: isize);
+}
+
+pub trait AsyncInputSource {
+    fn poll_input(&mut self, cx: &mut Context<'_>) -> Poll<Option<isize>>;
+}
+
+pub trait AsyncOutputSink {
+    // Ready once the sink can take another value with `send`
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()>;
+    fn send(&mut self, value: isize);
+}
+
+impl InputSource for VecDeque<isize> {
+    fn next_input(&mut self) -> Option<isize> {
+        self.pop_front()
+    }
+}
+
+impl<F: FnMut() -> Option<isize>> InputSource for F {
+    fn next_input(&mut self) -> Option<isize> {
+        self()
+    }
+}
+
+// Blocks until a value is sent, or every sender has gone away.
+impl InputSource for mpsc::Receiver<isize> {
+    fn next_input(&mut self) -> Option<isize> {
+        self.recv().ok()
+    }
+}
+
+pub struct Iter<I>(pub I);
+
+impl<I: Iterator<Item = isize>> InputSource for Iter<I> {
+    fn next_input(&mut self) -> Option<isize> {
+        self.0.next()
+    }
+}
+
+impl OutputSink for Vec<isize> {
+    fn output(&mut self, value: isize) {
+        self.push(value);
+    }
+}
+
+impl OutputSink for VecDeque<isize> {
+    fn output(&mut self, value: isize) {
+        self.push_back(value);
+    }
+}
+
+impl<F: FnMut(isize)> OutputSink for F {
+    fn output(&mut self, value: isize) {
+        self(value)
+    }
+}
+
+// Output sent after the receiver has gone away is dropped.
+impl OutputSink for mpsc::Sender<isize> {
+    fn output(&mut self, value: isize) {
+        let _ = mpsc::Sender::send(self, value);
+    }
+}
+
+impl OutputSink for mpsc::SyncSender<isize> {
+    fn output(&mut self, value: isize) {
+        let _ = mpsc::SyncSender::send(self, value);
+    }
+}
+
+impl<S: Stream<Item = isize> + Unpin> AsyncInputSource for S {
+    fn poll_input(&mut self, cx: &mut Context<'_>) -> Poll<Option<isize>> {
+        Pin::new(self).poll_next(cx)
+    }
+}
+
+// A synchronous source, for use where an asynchronous one is wanted.  It must not block.
+pub struct Immediate<T>(pub T);
+
+impl<T: InputSource> AsyncInputSource for Immediate<T> {
+    fn poll_input(&mut self, _cx: &mut Context<'_>) -> Poll<Option<isize>> {
+        Poll::Ready(self.0.next_input())
+    }
+}
+
+impl<T: OutputSink> AsyncOutputSink for T {
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    fn send(&mut self, value: isize) {
+        self.output(value)
+    }
+}
+
+// As with the std channels, output sent after the receiver has gone away is dropped.
+impl AsyncOutputSink for async_mpsc::Sender<isize> {
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        async_mpsc::Sender::poll_ready(self, cx).map(|_| ())
+    }
+
+    fn send(&mut self, value: isize) {
+        let _ = self.start_send(value);
+    }
+}
+
+impl AsyncOutputSink for async_mpsc::UnboundedSender<isize> {
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    fn send(&mut self, value: isize) {
+        let _ = self.unbounded_send(value);
+    }
+}
+
+struct Pair<'a, I, O> {
+    input: &'a mut I,
+    output: &'a mut O,
+}
+
+impl<I: InputSource, O> InputSource for Pair<'_, I, O> {
+    fn next_input(&mut self) -> Option<isize> {
+        self.input.next_input()
+    }
+}
+
+impl<I, O: OutputSink> OutputSink for Pair<'_, I, O> {
+    fn output(&mut self, value: isize) {
+        self.output.output(value)
+    }
+}
+
+// Each of these runs until the program halts, returning State::Halted, or wants input that the
+// source does not have, returning State::NeedsInput.  Input already pushed onto the machine is
+// read before asking the source for more.
+impl Machine {
+    pub fn run_with_io<I: InputSource, O: OutputSink>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<State, Error> {
+        self.run_with_device(&mut Pair { input, output })
+    }
+
+    pub fn run_with_device<D: InputSource + OutputSink>(
+        &mut self,
+        device: &mut D,
+    ) -> Result<State, Error> {
+        loop {
+            match self.run_until_io()? {
+                State::NeedsInput => match device.next_input() {
+                    Some(x) => self.push_input(x),
+                    None => return Ok(State::NeedsInput),
+                },
+                State::Output(x) => device.output(x),
+                State::Halted => return Ok(State::Halted),
+                State::Running => (),
+            }
+        }
+    }
+
+    pub async fn run_with_async_io<I: AsyncInputSource, O: AsyncOutputSink>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<State, Error> {
+        loop {
+            match self.run_until_io()? {
+                State::NeedsInput => match poll_fn(|cx| input.poll_input(cx)).await {
+                    Some(x) => self.push_input(x),
+                    None => return Ok(State::NeedsInput),
+                },
+                State::Output(x) => {
+                    poll_fn(|cx| output.poll_ready(cx)).await;
+                    output.send(x);
+                }
+                State::Halted => return Ok(State::Halted),
+                State::Running => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    use super::{Immediate, InputSource, Iter, OutputSink};
+    use crate::{Machine, State};
+
+    // reads two numbers and outputs their sum, forever
+    fn adder() -> Machine {
+        Machine::new(vec![3, 13, 3, 14, 1, 13, 14, 13, 4, 13, 1105, 1, 0, 0, 0])
+    }
+
+    #[test]
+    fn sync() {
+        let mut machine = adder();
+        let mut input: VecDeque<isize> = vec![1, 2, 3, 4, 5].into();
+        let mut output = Vec::new();
+        assert_eq!(
+            machine.run_with_io(&mut input, &mut output),
+            Ok(State::NeedsInput)
+        );
+        assert_eq!(output, vec![3, 7]);
+
+        let mut total = 0;
+        machine
+            .run_with_io(&mut Iter(vec![10].into_iter()), &mut |x| total += x)
+            .unwrap();
+        assert_eq!(total, 15);
+
+        let (send_input, mut receive_input) = std::sync::mpsc::channel();
+        let (mut send_output, receive_output) = std::sync::mpsc::channel();
+        let thread =
+            std::thread::spawn(move || machine.run_with_io(&mut receive_input, &mut send_output));
+        send_input.send(100).unwrap();
+        send_input.send(200).unwrap();
+        assert_eq!(receive_output.recv(), Ok(300));
+        drop(send_input);
+        assert_eq!(thread.join().unwrap(), Ok(State::NeedsInput));
+    }
+
+    #[test]
+    fn device() {
+        // sends its last output plus one as its next input, until it sees 10
+        struct Counter(isize);
+        impl InputSource for Counter {
+            fn next_input(&mut self) -> Option<isize> {
+                if self.0 < 10 {
+                    Some(self.0 + 1)
+                } else {
+                    None
+                }
+            }
+        }
+        impl OutputSink for Counter {
+            fn output(&mut self, value: isize) {
+                self.0 = value;
+            }
+        }
+
+        let mut counter = Counter(0);
+        let mut machine = adder();
+        machine.push_input(0);
+        assert_eq!(machine.run_with_device(&mut counter), Ok(State::NeedsInput));
+        // 0+1, 2+2, 5+5
+        assert_eq!(counter.0, 10);
+    }
+
+    #[test]
+    fn asynchronous() {
+        let mut machine = adder();
+        let (mut sender, receiver) = futures::channel::mpsc::unbounded();
+        let mut input = futures::stream::iter(vec![1, 2, 3, 4]);
+        assert_eq!(
+            block_on(machine.run_with_async_io(&mut input, &mut sender)),
+            Ok(State::NeedsInput)
+        );
+
+        let mut output = Vec::new();
+        let mut input = Immediate(VecDeque::from(vec![5, 6]));
+        assert_eq!(
+            block_on(machine.run_with_async_io(&mut input, &mut output)),
+            Ok(State::NeedsInput)
+        );
+        drop(sender);
+        assert_eq!(block_on(receiver.collect::<Vec<_>>()), vec![3, 7]);
+        assert_eq!(output, vec![11]);
+    }
+}
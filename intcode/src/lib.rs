@@ -1,202 +1,234 @@
 use std::collections::VecDeque;
-use std::convert::TryInto;
-
-use futures::{Stream, StreamExt};
+use std::fmt;
+
+use futures::future::poll_fn;
+use futures::Stream;
+
+pub mod ascii;
+pub mod asm;
+pub mod cfg;
+pub mod coverage;
+pub mod debugger;
+pub mod disasm;
+pub mod fast;
+pub mod fuzz;
+pub mod history;
+pub mod instruction;
+pub mod io;
+mod limits;
+mod machine;
+pub mod memory;
+pub mod network;
+pub mod opcodes;
+pub mod profile;
+pub mod selfmod;
+pub mod snapshot;
+pub mod symbolic;
+pub mod taint;
+pub mod trace;
+pub mod transpile;
+mod word;
+
+pub use limits::{Limit, Limits};
+pub use machine::{Machine, NoObserver, Observer, State};
+pub use memory::Memory;
+pub use word::{Arithmetic, Word};
 
 #[derive(Debug)]
 pub enum Status {
     Terminated(Vec<isize>),
     Output(isize),
+    Fault(Error),
+}
+
+// A failure of the interpreter: where it happened, what instruction was being executed, and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error<W = isize> {
+    pub ip: usize,
+    pub instruction: W,
+    pub kind: ErrorKind<W>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind<W = isize> {
+    UnknownOpcode(isize),
+    // `parameter` is 1-based, counting from the word after the instruction
+    InvalidMode { parameter: usize, mode: isize },
+    InvalidAddress(W),
+    InvalidJump(W),
+    InsufficientInput,
+    // only with Arithmetic::Checked
+    Overflow,
+    LimitExceeded(Limit),
+    // the final memory reaches this far, which is too far to return as a Vec; see memory::MAX_VEC
+    MemoryTooLarge(usize),
+    // raised by an extra opcode's handler
+    Custom(String),
+}
+
+impl<W: fmt::Display> fmt::Display for Error<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at ip {} (instruction {})",
+            self.kind, self.ip, self.instruction
+        )
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for ErrorKind<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownOpcode(x) => write!(f, "unexpected opcode {}", x),
+            ErrorKind::InvalidMode { parameter, mode } => {
+                write!(f, "invalid mode {} for parameter {}", mode, parameter)
+            }
+            ErrorKind::InvalidAddress(x) => write!(f, "un-indexable memory offset {}", x),
+            ErrorKind::InvalidJump(x) => write!(f, "invalid jump address {}", x),
+            ErrorKind::InsufficientInput => write!(f, "insufficient input provided"),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            ErrorKind::LimitExceeded(limit) => write!(f, "{}", limit),
+            ErrorKind::MemoryTooLarge(extent) => {
+                write!(f, "memory extends to {}, too far to copy out", extent)
+            }
+            ErrorKind::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> std::error::Error for Error<W> {}
+
 pub fn parse_opcodes(input: &str) -> Vec<isize> {
+    parse_words(input)
+}
+
+// parse_opcodes, for machines with a Word other than isize
+pub fn parse_words<W: Word>(input: &str) -> Vec<W> {
     input
         .trim()
         .split(',')
-        .map(|x| x.parse().expect("non-integer found"))
+        .map(|x| x.parse().unwrap_or_else(|_| panic!("non-integer found")))
         .collect()
 }
 
+// The inverse of parse_opcodes
+pub fn format_opcodes<W: fmt::Display>(opcodes: &[W]) -> String {
+    let opcodes: Vec<String> = opcodes.iter().map(|x| x.to_string()).collect();
+    opcodes.join(",")
+}
+
 pub fn run(opcodes: Vec<isize>) -> Vec<isize> {
-    let (memory, _output) = run_with_io(opcodes, VecDeque::new());
-    memory
+    try_run(opcodes).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_run(opcodes: Vec<isize>) -> Result<Vec<isize>, Error> {
+    let (memory, _output) = try_run_with_io(opcodes, VecDeque::new())?;
+    Ok(memory)
 }
 
 // Returns (memory, output)
 pub fn run_with_io(opcodes: Vec<isize>, input: VecDeque<isize>) -> (Vec<isize>, Vec<isize>) {
-    let status = stream_with_io(opcodes, Box::new(futures::stream::iter(input)));
-
-    let mut output = Vec::new();
-    let mut memory = Vec::new();
+    try_run_with_io(opcodes, input).unwrap_or_else(|e| panic!("{}", e))
+}
 
-    for s in futures::executor::block_on_stream(Box::pin(status)) {
-        match s {
-            Status::Terminated(x) => memory = x,
-            Status::Output(x) => output.push(x),
+// Keeps to the (memory, output) that the fuzzer and transpiled code also return, so it does not
+// count cycles; run_with_limits is the runner that reports them.
+pub fn try_run_with_io(
+    opcodes: Vec<isize>,
+    input: VecDeque<isize>,
+) -> Result<(Vec<isize>, Vec<isize>), Error> {
+    let mut machine = fast::FastMachine::new(opcodes);
+    machine.extend_input(input);
+
+    match machine.run_until_blocked()? {
+        State::Halted => {
+            let output = machine.take_output();
+            Ok((machine.try_into_memory()?, output))
         }
+        _ => Err(machine.fault(ErrorKind::InsufficientInput)),
     }
-
-    (memory, output)
 }
 
-struct InterpreterState {
-    opcodes: Vec<isize>,
-    input: Box<dyn Stream<Item = isize> + Unpin>,
-    ip: usize,
-    relative_base: isize,
-    done: bool,
+// Everything a program that ran to completion left behind.  Only run_with_limits returns one;
+// the other run_* and try_run* functions do not report cycles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Run {
+    pub memory: Vec<isize>,
+    pub output: Vec<isize>,
+    // instructions retired, including the final halt
+    pub cycles: u64,
 }
 
-pub fn stream_with_io(
-    opcodes: Vec<isize>,
-    input: Box<dyn Stream<Item = isize> + Unpin>,
-) -> impl Stream<Item = Status> + Unpin {
-    Box::pin(futures::stream::unfold(
-        InterpreterState {
-            opcodes,
-            input,
-            ip: 0,
-            relative_base: 0,
-            done: false,
-        },
-        next_opcode,
-    ))
+#[derive(Debug)]
+pub enum Outcome {
+    Halted(Run),
+    // The machine is paused on the instruction that would have gone over the limit; raising the
+    // limit and resuming it picks up where it left off.  Output so far is still buffered in it.
+    LimitExceeded(Limit, Box<Machine>),
+    Fault(Error),
 }
 
-async fn next_opcode(mut state: InterpreterState) -> Option<(Status, InterpreterState)> {
-    if state.done {
-        return None;
-    }
-
-    loop {
-        match state.opcodes[state.ip] % 100 {
-            1 => {
-                let (source1, source2, destination) = state.consume_operands_3();
-                state.opcodes[destination] = source1 + source2;
-            }
-            2 => {
-                let (source1, source2, destination) = state.consume_operands_3();
-                state.opcodes[destination] = source1 * source2;
-            }
-            3 => {
-                let destination = state.get_write_index_at(1);
-                state.opcodes[destination] = state
-                    .input
-                    .next()
-                    .await
-                    .expect("insufficient input provided");
-                state.ip += 2;
-            }
-            4 => {
-                let source = state.get_read_operand_at(1);
-                state.ip += 2;
-                return Some((Status::Output(source), state));
-            }
-            5 => {
-                let (comparison, target) = state.consume_operands_2();
-                if comparison != 0 {
-                    state.ip = target.try_into().expect("invalid jump address");
-                }
-            }
-            6 => {
-                let (comparison, target) = state.consume_operands_2();
-                if comparison == 0 {
-                    state.ip = target.try_into().expect("invalid jump address");
-                }
+// try_run_with_io, but gives up on programs that run too long or use too much memory.
+pub fn run_with_limits(opcodes: Vec<isize>, input: VecDeque<isize>, limits: Limits) -> Outcome {
+    let mut machine = Machine::new(opcodes);
+    machine.extend_input(input);
+    machine.set_limits(limits);
+
+    match machine.run_until_blocked() {
+        Ok(State::Halted) => {
+            let output = machine.take_output();
+            let cycles = machine.cycles();
+            match machine.try_into_memory() {
+                Ok(memory) => Outcome::Halted(Run {
+                    memory,
+                    output,
+                    cycles,
+                }),
+                Err(e) => Outcome::Fault(e),
             }
-            7 => {
-                let (source1, source2, destination) = state.consume_operands_3();
-                if source1 < source2 {
-                    state.opcodes[destination] = 1;
-                } else {
-                    state.opcodes[destination] = 0;
-                }
-            }
-            8 => {
-                let (source1, source2, destination) = state.consume_operands_3();
-                if source1 == source2 {
-                    state.opcodes[destination] = 1;
-                } else {
-                    state.opcodes[destination] = 0;
-                }
-            }
-            9 => {
-                let source = state.get_read_operand_at(1);
-                state.relative_base += source;
-                state.ip += 2;
-            }
-            99 => {
-                return Some((
-                    Status::Terminated(state.opcodes),
-                    InterpreterState {
-                        done: true,
-                        opcodes: vec![],
-                        ..state
-                    },
-                ))
-            }
-            x => panic!("unexpected opcode found in position {}: {}", state.ip, x),
         }
+        Ok(_) => Outcome::Fault(machine.fault(ErrorKind::InsufficientInput)),
+        Err(Error {
+            kind: ErrorKind::LimitExceeded(limit),
+            ..
+        }) => Outcome::LimitExceeded(limit, Box::new(machine)),
+        Err(e) => Outcome::Fault(e),
     }
 }
 
-impl InterpreterState {
-    fn consume_operands_3(&mut self) -> (isize, isize, usize) {
-        let source1 = self.get_read_operand_at(1);
-        let source2 = self.get_read_operand_at(2);
-        let destination = self.get_write_index_at(3);
-
-        self.ip += 4;
-
-        (source1, source2, destination)
-    }
-
-    fn consume_operands_2(&mut self) -> (isize, isize) {
-        let source1 = self.get_read_operand_at(1);
-        let source2 = self.get_read_operand_at(2);
-
-        self.ip += 3;
-
-        (source1, source2)
-    }
+pub fn stream_with_io<I: io::AsyncInputSource>(
+    opcodes: Vec<isize>,
+    input: I,
+) -> impl Stream<Item = Status> + Unpin {
+    Box::pin(futures::stream::unfold(
+        Some((Machine::new(opcodes), input)),
+        next_status,
+    ))
+}
 
-    fn get_read_operand_at(&mut self, idx: usize) -> isize {
-        let source_idx = self.opcodes[self.ip + idx];
-        match self.opcodes[self.ip] / 10isize.pow((idx + 1).try_into().unwrap()) % 10 {
-            0 => {
-                let source_idx: usize = source_idx.try_into().expect("un-indexable memory offset");
-                if source_idx >= self.opcodes.len() {
-                    self.opcodes.resize(source_idx + 1, 0);
-                }
-                self.opcodes[source_idx]
-            }
-            1 => source_idx,
-            2 => {
-                let source_idx: usize = (source_idx + self.relative_base)
-                    .try_into()
-                    .expect("un-indexable memory offset");
-                self.opcodes[source_idx]
-            }
-            x => panic!("Invalid parameter mode {} at ip {}", x, self.ip),
+// The stream ends after it has yielded either Status::Terminated or Status::Fault.
+async fn next_status<I: io::AsyncInputSource>(
+    state: Option<(Machine, I)>,
+) -> Option<(Status, Option<(Machine, I)>)> {
+    let (mut machine, mut input) = state?;
+
+    let status = loop {
+        match machine.run_until_io() {
+            Ok(State::NeedsInput) => match poll_fn(|cx| input.poll_input(cx)).await {
+                Some(x) => machine.push_input(x),
+                None => break Status::Fault(machine.fault(ErrorKind::InsufficientInput)),
+            },
+            Ok(State::Output(x)) => return Some((Status::Output(x), Some((machine, input)))),
+            Ok(State::Halted) => match machine.try_into_memory() {
+                Ok(memory) => break Status::Terminated(memory),
+                Err(e) => break Status::Fault(e),
+            },
+            Ok(State::Running) => (),
+            Err(e) => break Status::Fault(e),
         }
-    }
+    };
 
-    fn get_write_index_at(&mut self, idx: usize) -> usize {
-        let destination_idx = self.opcodes[self.ip + idx];
-        let index = match self.opcodes[self.ip] / 10isize.pow((idx + 1).try_into().unwrap()) % 10 {
-            0 => destination_idx
-                .try_into()
-                .expect("un-indexable memory offset"),
-            2 => (destination_idx + self.relative_base)
-                .try_into()
-                .expect("un-indexable memory offset"),
-            x => panic!("Invalid destination parameter mode {} at ip {}", x, self.ip),
-        };
-        if index >= self.opcodes.len() {
-            self.opcodes.resize(index + 1, 0);
-        }
-        index
-    }
+    Some((status, None))
 }
 
 #[cfg(test)]
@@ -209,6 +241,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn formatter() {
+        let text = "1,9,10,3,2,3,11,0,99,-30,40,50";
+        assert_eq!(super::format_opcodes(&super::parse_opcodes(text)), text);
+    }
+
     #[test]
     fn run() {
         assert_eq!(
@@ -309,4 +347,112 @@ mod tests {
         /* "should output the large number in the middle" */
         assert_eq!(output, vec![prog[1]]);
     }
+
+    #[test]
+    fn faults() {
+        use super::{Error, ErrorKind};
+
+        assert_eq!(
+            super::try_run(vec![1, 0, 0, 0, 42]),
+            Err(Error {
+                ip: 4,
+                instruction: 42,
+                kind: ErrorKind::UnknownOpcode(42)
+            })
+        );
+        assert_eq!(
+            super::try_run(vec![301, 0, 0, 0, 99]).unwrap_err().kind,
+            ErrorKind::InvalidMode {
+                parameter: 1,
+                mode: 3
+            }
+        );
+        assert_eq!(
+            super::try_run(vec![11101, 0, 0, 0, 99]).unwrap_err().kind,
+            ErrorKind::InvalidMode {
+                parameter: 3,
+                mode: 1
+            }
+        );
+        assert_eq!(
+            super::try_run(vec![1, -1, 0, 0, 99]).unwrap_err().kind,
+            ErrorKind::InvalidAddress(-1)
+        );
+        assert_eq!(
+            super::try_run(vec![1105, 1, -7, 99]),
+            Err(Error {
+                ip: 0,
+                instruction: 1105,
+                kind: ErrorKind::InvalidJump(-7)
+            })
+        );
+        assert_eq!(
+            super::try_run_with_io(vec![3, 0, 3, 0, 99], vec![1].into()),
+            Err(Error {
+                ip: 2,
+                instruction: 3,
+                kind: ErrorKind::InsufficientInput
+            })
+        );
+    }
+
+    #[test]
+    fn huge_address() {
+        use super::{Error, ErrorKind, Outcome};
+
+        // the machine copes with this, but the memory cannot be handed back as a Vec
+        let program = vec![1101, 1, 2, 1_000_000_000_000, 99];
+        assert_eq!(
+            super::try_run(program.clone()),
+            Err(Error {
+                ip: 4,
+                instruction: 99,
+                kind: ErrorKind::MemoryTooLarge(1_000_000_000_001)
+            })
+        );
+        assert!(matches!(
+            super::run_with_limits(program.clone(), vec![].into(), Default::default()),
+            Outcome::Fault(Error {
+                kind: ErrorKind::MemoryTooLarge(_),
+                ..
+            })
+        ));
+        let result = std::panic::catch_unwind(|| super::run_with_io(program, vec![].into()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn fault_ends_stream() {
+        let status = super::stream_with_io(vec![104, 7, 0], Box::new(futures::stream::empty()));
+        let status: Vec<_> = futures::executor::block_on_stream(status).collect();
+        assert_eq!(status.len(), 2);
+        assert!(matches!(status[0], super::Status::Output(7)));
+        assert!(matches!(status[1], super::Status::Fault(_)));
+    }
+
+    #[test]
+    fn run_with_limits() {
+        use super::{Limit, Limits, Outcome};
+
+        let limits = Limits {
+            instructions: Some(100),
+            ..Limits::default()
+        };
+        match super::run_with_limits(vec![3, 0, 4, 0, 99], vec![7].into(), limits) {
+            Outcome::Halted(run) => {
+                assert_eq!(run.output, vec![7]);
+                assert_eq!(run.cycles, 3);
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+
+        // loops forever, printing 1
+        match super::run_with_limits(vec![104, 1, 1105, 1, 0], vec![].into(), limits) {
+            Outcome::LimitExceeded(Limit::Instructions(100), mut machine) => {
+                assert_eq!(machine.cycles(), 100);
+                assert_eq!(machine.take_output(), vec![1; 50]);
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+    }
 }
@@ -0,0 +1,31 @@
+use std::fmt;
+
+// Bounds on how far a Machine may run, set with Machine::set_limits, e.g.
+// `Limits { instructions: Some(1_000_000), ..Limits::default() }`.  Hitting one is reported as
+// ErrorKind::LimitExceeded, with the machine left paused just before the offending instruction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    // the most instructions the machine may retire, counting from when it was created rather
+    // than from the start of each run: it is compared with Machine::cycles.  So a machine that
+    // has already retired that many stops straight away when resumed; to give it another n,
+    // set this to `Some(machine.cycles() + n)`.
+    pub instructions: Option<u64>,
+    // writes at or beyond this address fault
+    pub memory: Option<usize>,
+}
+
+// Which limit was hit, and what it was set to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Instructions(u64),
+    Memory(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions(x) => write!(f, "instruction limit of {} exceeded", x),
+            Limit::Memory(x) => write!(f, "memory limit of {} words exceeded", x),
+        }
+    }
+}
@@ -0,0 +1,692 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use crate::instruction::Mode;
+use crate::memory::{AutoMemory, Memory};
+use crate::opcodes::{Action, Opcodes, Operands};
+use crate::{Arithmetic, Error, ErrorKind, Limit, Limits, Word};
+
+// What the machine is doing after a step.  `Running` only ever comes out of `step`; the `run_*`
+// methods keep going until something more interesting happens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State<W = isize> {
+    Running,
+    NeedsInput,
+    Output(W),
+    Halted,
+}
+
+// Callbacks for watching a Machine execute, passed to the `*_with` methods.  For each instruction
+// that retires, the machine calls `begin`, then `operand` for each operand in order (with the
+// value read, or for a destination, the address written), then `input`, `write` and
+// `relative_base` for any side effects, and finally `retire` with the next ip.  An instruction that faults or blocks
+// on input gets a `begin` and possibly some `operand`s but no `retire`.
+//
+// Every callback does nothing by default, and the plain `step`/`run_*` methods use NoObserver, so
+// observing costs nothing unless it is asked for.
+pub trait Observer<W = isize> {
+    fn begin(&mut self, _ip: usize, _instruction: &W) {}
+    fn operand(&mut self, _mode: Mode, _parameter: &W, _value: &W) {}
+    // a value taken from the input queue
+    fn input(&mut self, _value: &W) {}
+    fn write(&mut self, _address: usize, _old: &W, _new: &W) {}
+    fn relative_base(&mut self, _old: &W, _new: &W) {}
+    fn retire(&mut self, _next_ip: usize) {}
+}
+
+pub struct NoObserver;
+
+impl<W> Observer<W> for NoObserver {}
+
+impl<W, O: Observer<W> + ?Sized> Observer<W> for &mut O {
+    fn begin(&mut self, ip: usize, instruction: &W) {
+        (**self).begin(ip, instruction)
+    }
+    fn operand(&mut self, mode: Mode, parameter: &W, value: &W) {
+        (**self).operand(mode, parameter, value)
+    }
+    fn input(&mut self, value: &W) {
+        (**self).input(value)
+    }
+    fn write(&mut self, address: usize, old: &W, new: &W) {
+        (**self).write(address, old, new)
+    }
+    fn relative_base(&mut self, old: &W, new: &W) {
+        (**self).relative_base(old, new)
+    }
+    fn retire(&mut self, next_ip: usize) {
+        (**self).retire(next_ip)
+    }
+}
+
+// Two observers watching the same machine, e.g. `machine.step_with(&mut (&mut a, &mut b))`
+impl<W, A: Observer<W>, B: Observer<W>> Observer<W> for (A, B) {
+    fn begin(&mut self, ip: usize, instruction: &W) {
+        self.0.begin(ip, instruction);
+        self.1.begin(ip, instruction);
+    }
+    fn operand(&mut self, mode: Mode, parameter: &W, value: &W) {
+        self.0.operand(mode, parameter, value);
+        self.1.operand(mode, parameter, value);
+    }
+    fn input(&mut self, value: &W) {
+        self.0.input(value);
+        self.1.input(value);
+    }
+    fn write(&mut self, address: usize, old: &W, new: &W) {
+        self.0.write(address, old, new);
+        self.1.write(address, old, new);
+    }
+    fn relative_base(&mut self, old: &W, new: &W) {
+        self.0.relative_base(old, new);
+        self.1.relative_base(old, new);
+    }
+    fn retire(&mut self, next_ip: usize) {
+        self.0.retire(next_ip);
+        self.1.retire(next_ip);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Machine<W = isize, M = AutoMemory<W>> {
+    memory: M,
+    ip: usize,
+    relative_base: W,
+    input: VecDeque<W>,
+    output: VecDeque<W>,
+    halted: bool,
+    arithmetic: Arithmetic,
+    limits: Limits,
+    cycles: u64,
+    opcodes: Option<Arc<Opcodes<W, M>>>,
+}
+
+impl Machine {
+    pub fn new(program: Vec<isize>) -> Machine {
+        Machine::with_memory(AutoMemory::from(program))
+    }
+}
+
+impl<W: Word> Machine<W> {
+    // Machine::new for other word types, e.g. `Machine::<BigInt>::with_program(parse_words(s))`
+    pub fn with_program(program: Vec<W>) -> Machine<W> {
+        Machine::with_memory(AutoMemory::from(program))
+    }
+}
+
+impl<W: Word, M: Memory<W>> Machine<W, M> {
+    // For choosing a memory backend explicitly, e.g. `Machine::with_memory(PagedMemory::from(p))`
+    pub fn with_memory(memory: M) -> Machine<W, M> {
+        Machine {
+            memory,
+            ip: 0,
+            relative_base: W::default(),
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            halted: false,
+            arithmetic: Arithmetic::default(),
+            limits: Limits::default(),
+            cycles: 0,
+            opcodes: None,
+        }
+    }
+
+    pub fn push_input(&mut self, value: W) {
+        self.input.push_back(value);
+    }
+
+    pub fn extend_input<I: IntoIterator<Item = W>>(&mut self, values: I) {
+        self.input.extend(values);
+    }
+
+    // Only `run_until_blocked` buffers output; `step` and `run_until_io` hand it straight back.
+    pub fn pop_output(&mut self) -> Option<W> {
+        self.output.pop_front()
+    }
+
+    pub fn take_output(&mut self) -> Vec<W> {
+        self.output.drain(..).collect()
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> W {
+        self.relative_base.clone()
+    }
+
+    pub fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
+    }
+
+    pub fn set_relative_base(&mut self, relative_base: W) {
+        self.relative_base = relative_base;
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    // The instruction limit is on the total of cycles(), not on what runs after this call.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    // Extra opcodes, on top of (or instead of) the built-in ones; see the opcodes module.
+    pub fn set_opcodes(&mut self, opcodes: Opcodes<W, M>) {
+        self.opcodes = Some(Arc::new(opcodes));
+    }
+
+    // How many instructions have retired since the machine was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // For restoring a snapshot.
+    pub(crate) fn set_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    pub(crate) fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    pub fn pending_input(&self) -> &VecDeque<W> {
+        &self.input
+    }
+
+    pub(crate) fn pop_input(&mut self, observer: &mut dyn Observer<W>) -> Option<W> {
+        let value = self.input.pop_front()?;
+        observer.input(&value);
+        Some(value)
+    }
+
+    // Puts the machine back to before an instruction retired, for History; the caller restores
+    // memory and the relative base.  `inputs` are those the instruction consumed, in order.
+    pub(crate) fn unretire(&mut self, ip: usize, inputs: Vec<W>) {
+        self.ip = ip;
+        self.halted = false;
+        self.cycles -= 1;
+        for x in inputs.into_iter().rev() {
+            self.input.push_front(x);
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    // Panics if memory is too large to copy out; see try_into_memory.
+    pub fn into_memory(self) -> Vec<W> {
+        self.try_into_memory().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Everything up to the highest address written, or ErrorKind::MemoryTooLarge if that is so far
+    // that it would not fit in a Vec.  memory() can still look at it.
+    pub fn try_into_memory(self) -> Result<Vec<W>, Error<W>> {
+        match self.memory.try_to_vec() {
+            Some(memory) => Ok(memory),
+            None => Err(self.fault(ErrorKind::MemoryTooLarge(self.memory.extent()))),
+        }
+    }
+
+    // Memory beyond what the program has written to reads as zero.
+    pub fn read(&self, address: usize) -> W {
+        self.memory.read(address)
+    }
+
+    pub fn write(&mut self, address: usize, value: W) {
+        self.memory.write(address, value);
+    }
+
+    // Builds an Error pointing at the instruction the machine is currently stopped on.
+    pub fn fault(&self, kind: ErrorKind<W>) -> Error<W> {
+        Error {
+            ip: self.ip,
+            instruction: self.read(self.ip),
+            kind,
+        }
+    }
+
+    // Executes a single instruction.  If it fails, or it needs input that has not been pushed yet,
+    // the machine is left exactly as it was so the caller can fix things up and try again.
+    pub fn step(&mut self) -> Result<State<W>, Error<W>> {
+        self.step_with(&mut NoObserver)
+    }
+
+    pub fn run_until_io(&mut self) -> Result<State<W>, Error<W>> {
+        self.run_until_io_with(&mut NoObserver)
+    }
+
+    // Runs until the program halts or wants input that has not been provided, buffering any
+    // output along the way.  Returns either State::NeedsInput or State::Halted.
+    pub fn run_until_blocked(&mut self) -> Result<State<W>, Error<W>> {
+        self.run_until_blocked_with(&mut NoObserver)
+    }
+
+    pub fn step_with<O: Observer<W>>(&mut self, observer: &mut O) -> Result<State<W>, Error<W>> {
+        if self.halted {
+            return Ok(State::Halted);
+        }
+        if let Some(max) = self.limits.instructions {
+            if self.cycles >= max {
+                return Err(self.fault(ErrorKind::LimitExceeded(Limit::Instructions(max))));
+            }
+        }
+
+        self.execute(observer).map_err(|kind| self.fault(kind))
+    }
+
+    pub fn run_until_io_with<O: Observer<W>>(
+        &mut self,
+        observer: &mut O,
+    ) -> Result<State<W>, Error<W>> {
+        loop {
+            match self.step_with(observer)? {
+                State::Running => (),
+                x => return Ok(x),
+            }
+        }
+    }
+
+    pub fn run_until_blocked_with<O: Observer<W>>(
+        &mut self,
+        observer: &mut O,
+    ) -> Result<State<W>, Error<W>> {
+        loop {
+            match self.run_until_io_with(observer)? {
+                State::Output(x) => self.output.push_back(x),
+                x => return Ok(x),
+            }
+        }
+    }
+
+    fn execute<O: Observer<W>>(&mut self, observer: &mut O) -> Result<State<W>, ErrorKind<W>> {
+        let instruction = self.read(self.ip);
+        observer.begin(self.ip, &instruction);
+
+        let opcode = instruction.low_digits() % 100;
+        if let Some((arity, handler)) = self.opcodes.as_ref().and_then(|o| o.lookup(opcode)) {
+            let action = handler(&mut Operands {
+                machine: self,
+                observer,
+                opcode,
+                arity,
+            })?;
+            let next = self.ip + arity.map_or(1, |arity| arity + 1);
+            let state = match action {
+                Action::Next => {
+                    self.ip = next;
+                    State::Running
+                }
+                Action::Jump(target) => {
+                    self.ip = target;
+                    State::Running
+                }
+                Action::Output(x) => {
+                    self.ip = next;
+                    State::Output(x)
+                }
+                Action::NeedsInput => return Ok(State::NeedsInput),
+                Action::Halt => {
+                    self.halted = true;
+                    State::Halted
+                }
+            };
+            self.cycles += 1;
+            observer.retire(self.ip);
+            return Ok(state);
+        }
+
+        let state = match opcode {
+            1 => {
+                let (source1, source2, destination) = self.get_operands_3(observer)?;
+                let value = self.add(&source1, &source2)?;
+                self.store(destination, value, observer);
+                self.ip += 4;
+                State::Running
+            }
+            2 => {
+                let (source1, source2, destination) = self.get_operands_3(observer)?;
+                let value = self.mul(&source1, &source2)?;
+                self.store(destination, value, observer);
+                self.ip += 4;
+                State::Running
+            }
+            3 => {
+                let destination = self.get_write_index_at(1, observer)?;
+                let value = match self.pop_input(observer) {
+                    Some(x) => x,
+                    None => return Ok(State::NeedsInput),
+                };
+                self.store(destination, value, observer);
+                self.ip += 2;
+                State::Running
+            }
+            4 => {
+                let source = self.get_read_operand_at(1, observer)?;
+                self.ip += 2;
+                State::Output(source)
+            }
+            5 => {
+                let (comparison, target) = self.get_operands_2(observer)?;
+                if !comparison.is_zero() {
+                    self.jump(target)?;
+                } else {
+                    self.ip += 3;
+                }
+                State::Running
+            }
+            6 => {
+                let (comparison, target) = self.get_operands_2(observer)?;
+                if comparison.is_zero() {
+                    self.jump(target)?;
+                } else {
+                    self.ip += 3;
+                }
+                State::Running
+            }
+            7 => {
+                let (source1, source2, destination) = self.get_operands_3(observer)?;
+                let value = W::from_isize((source1 < source2) as isize);
+                self.store(destination, value, observer);
+                self.ip += 4;
+                State::Running
+            }
+            8 => {
+                let (source1, source2, destination) = self.get_operands_3(observer)?;
+                let value = W::from_isize((source1 == source2) as isize);
+                self.store(destination, value, observer);
+                self.ip += 4;
+                State::Running
+            }
+            9 => {
+                let source = self.get_read_operand_at(1, observer)?;
+                let relative_base = self.add(&self.relative_base, &source)?;
+                observer.relative_base(&self.relative_base, &relative_base);
+                self.relative_base = relative_base;
+                self.ip += 2;
+                State::Running
+            }
+            99 => {
+                self.halted = true;
+                State::Halted
+            }
+            x => return Err(ErrorKind::UnknownOpcode(x)),
+        };
+
+        self.cycles += 1;
+        observer.retire(self.ip);
+        Ok(state)
+    }
+
+    // Arithmetic on words, which also covers relative addressing, honours self.arithmetic.
+    fn add(&self, a: &W, b: &W) -> Result<W, ErrorKind<W>> {
+        match self.arithmetic {
+            Arithmetic::Wrapping => Ok(a.wrapping_add(b)),
+            Arithmetic::Checked => a.checked_add(b).ok_or(ErrorKind::Overflow),
+        }
+    }
+
+    fn mul(&self, a: &W, b: &W) -> Result<W, ErrorKind<W>> {
+        match self.arithmetic {
+            Arithmetic::Wrapping => Ok(a.wrapping_mul(b)),
+            Arithmetic::Checked => a.checked_mul(b).ok_or(ErrorKind::Overflow),
+        }
+    }
+
+    pub(crate) fn store<O: Observer<W> + ?Sized>(
+        &mut self,
+        address: usize,
+        value: W,
+        observer: &mut O,
+    ) {
+        observer.write(address, &self.read(address), &value);
+        self.write(address, value);
+    }
+
+    fn jump(&mut self, target: W) -> Result<(), ErrorKind<W>> {
+        self.ip = target.to_usize().ok_or(ErrorKind::InvalidJump(target))?;
+        Ok(())
+    }
+
+    fn get_operands_3<O: Observer<W>>(
+        &self,
+        observer: &mut O,
+    ) -> Result<(W, W, usize), ErrorKind<W>> {
+        let source1 = self.get_read_operand_at(1, observer)?;
+        let source2 = self.get_read_operand_at(2, observer)?;
+        let destination = self.get_write_index_at(3, observer)?;
+
+        Ok((source1, source2, destination))
+    }
+
+    fn get_operands_2<O: Observer<W>>(&self, observer: &mut O) -> Result<(W, W), ErrorKind<W>> {
+        let source1 = self.get_read_operand_at(1, observer)?;
+        let source2 = self.get_read_operand_at(2, observer)?;
+
+        Ok((source1, source2))
+    }
+
+    fn mode_at(&self, idx: usize) -> Result<Mode, ErrorKind<W>> {
+        let instruction = self.read(self.ip).low_digits();
+        // parameters past the digits an instruction has, as a fallback handler may read, are
+        // in position mode like any other missing digit
+        let mode = u32::try_from(idx + 1)
+            .ok()
+            .and_then(|power| 10isize.checked_pow(power))
+            .map_or(0, |scale| instruction / scale % 10);
+        Mode::from_code(mode).ok_or(ErrorKind::InvalidMode {
+            parameter: idx,
+            mode,
+        })
+    }
+
+    pub(crate) fn get_read_operand_at<O: Observer<W> + ?Sized>(
+        &self,
+        idx: usize,
+        observer: &mut O,
+    ) -> Result<W, ErrorKind<W>> {
+        let source_idx = self.read(self.ip + idx);
+        let mode = self.mode_at(idx)?;
+        let value = match mode {
+            Mode::Position => self.read_address(source_idx.clone())?,
+            Mode::Immediate => source_idx.clone(),
+            Mode::Relative => self.read_address(self.add(&source_idx, &self.relative_base)?)?,
+        };
+        observer.operand(mode, &source_idx, &value);
+        Ok(value)
+    }
+
+    fn read_address(&self, address: W) -> Result<W, ErrorKind<W>> {
+        let address = address
+            .to_usize()
+            .ok_or(ErrorKind::InvalidAddress(address))?;
+        Ok(self.read(address))
+    }
+
+    pub(crate) fn get_write_index_at<O: Observer<W> + ?Sized>(
+        &self,
+        idx: usize,
+        observer: &mut O,
+    ) -> Result<usize, ErrorKind<W>> {
+        let destination_idx = self.read(self.ip + idx);
+        let mode = self.mode_at(idx)?;
+        let address = match mode {
+            Mode::Position => destination_idx.clone(),
+            Mode::Relative => self.add(&destination_idx, &self.relative_base)?,
+            Mode::Immediate => {
+                return Err(ErrorKind::InvalidMode {
+                    parameter: idx,
+                    mode: mode.code(),
+                })
+            }
+        };
+        observer.operand(mode, &destination_idx, &address);
+        let address = address
+            .to_usize()
+            .ok_or(ErrorKind::InvalidAddress(address))?;
+        match self.limits.memory {
+            Some(max) if address >= max => Err(ErrorKind::LimitExceeded(Limit::Memory(max))),
+            _ => Ok(address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Machine, State};
+    use crate::{Arithmetic, ErrorKind};
+    use num_bigint::BigInt;
+
+    #[test]
+    fn step_by_step() {
+        let mut machine = Machine::new(vec![3, 9, 1001, 9, 5, 9, 4, 9, 99, 0]);
+        assert_eq!(machine.step(), Ok(State::NeedsInput));
+        assert_eq!(machine.ip(), 0);
+
+        machine.push_input(10);
+        assert_eq!(machine.step(), Ok(State::Running));
+        assert_eq!(machine.ip(), 2);
+        assert_eq!(machine.step(), Ok(State::Running));
+        assert_eq!(machine.read(9), 15);
+        assert_eq!(machine.step(), Ok(State::Output(15)));
+        assert_eq!(machine.step(), Ok(State::Halted));
+        assert!(machine.is_halted());
+        assert_eq!(machine.step(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn run_until_io() {
+        // echoes every input back, doubled, forever
+        let mut machine = Machine::new(vec![3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0]);
+        assert_eq!(machine.run_until_io(), Ok(State::NeedsInput));
+        machine.extend_input(vec![1, 2]);
+        assert_eq!(machine.run_until_io(), Ok(State::Output(2)));
+        assert_eq!(machine.run_until_io(), Ok(State::Output(4)));
+        assert_eq!(machine.run_until_io(), Ok(State::NeedsInput));
+        assert_eq!(machine.pop_output(), None);
+    }
+
+    #[test]
+    fn run_until_blocked() {
+        let mut machine = Machine::new(vec![104, 1, 104, 2, 3, 0, 4, 0, 99]);
+        assert_eq!(machine.run_until_blocked(), Ok(State::NeedsInput));
+        assert_eq!(machine.take_output(), vec![1, 2]);
+
+        machine.push_input(3);
+        assert_eq!(machine.run_until_blocked(), Ok(State::Halted));
+        assert_eq!(machine.pop_output(), Some(3));
+        assert_eq!(machine.pop_output(), None);
+    }
+
+    #[test]
+    fn fault_leaves_state() {
+        let mut machine = Machine::new(vec![1101, 1, 2, 0, 22201, 0, 0, -1, 99]);
+        assert_eq!(machine.step(), Ok(State::Running));
+        let error = machine.step().unwrap_err();
+        assert_eq!(error.ip, 4);
+        assert_eq!(error.instruction, 22201);
+        assert_eq!(error.kind, ErrorKind::InvalidAddress(-1));
+        assert_eq!(machine.ip(), 4);
+        assert_eq!(machine.into_memory(), vec![3, 1, 2, 0, 22201, 0, 0, -1, 99]);
+    }
+
+    #[test]
+    fn memory_backends() {
+        use crate::memory::{DenseMemory, Memory, PagedMemory};
+
+        // writes to an address far beyond the end of the program
+        let program = vec![1101, 1, 2, 1_000_000_000_000, 4, 0, 99];
+        let mut machine = Machine::with_memory(PagedMemory::from(program.clone()));
+        assert_eq!(machine.run_until_io(), Ok(State::Output(1101)));
+        assert_eq!(machine.memory().read(1_000_000_000_000), 3);
+
+        let mut machine = Machine::new(program);
+        assert_eq!(machine.run_until_io(), Ok(State::Output(1101)));
+        assert_eq!(machine.read(1_000_000_000_000), 3);
+
+        let mut machine = Machine::with_memory(DenseMemory::from(vec![1101isize, 1, 2, 10, 99]));
+        assert_eq!(machine.run_until_io(), Ok(State::Halted));
+        assert_eq!(machine.memory().extent(), 11);
+    }
+
+    #[test]
+    fn word_types() {
+        // squares its input twice, which overflows 64 bits for anything over 2^16
+        let program = "3,100,2,100,100,100,2,100,100,100,4,100,99";
+        let input: i128 = 1 << 20;
+
+        let mut machine = Machine::<i128>::with_program(crate::parse_words(program));
+        machine.push_input(input);
+        assert_eq!(machine.run_until_io(), Ok(State::Output(1 << 80)));
+
+        let mut machine = Machine::<BigInt>::with_program(crate::parse_words(program));
+        machine.push_input(BigInt::from(1u64 << 40));
+        assert_eq!(
+            machine.run_until_io(),
+            Ok(State::Output(BigInt::from(1) << 160))
+        );
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let mut machine = Machine::<i64>::with_program(vec![1101, 1, 1, 9, 1002, 9, 2, 9, 99, 0]);
+        machine.set_arithmetic(Arithmetic::Checked);
+        assert_eq!(machine.step(), Ok(State::Running));
+        machine.write(9, i64::MAX);
+        let error = machine.step().unwrap_err();
+        assert_eq!(error.ip, 4);
+        assert_eq!(error.kind, ErrorKind::Overflow);
+        assert_eq!(machine.read(9), i64::MAX);
+
+        machine.set_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(machine.step(), Ok(State::Running));
+        assert_eq!(machine.read(9), -2);
+    }
+
+    #[test]
+    fn limits() {
+        use crate::{Limit, Limits};
+
+        let mut machine = Machine::new(vec![1101, 1, 1, 7, 1105, 1, 0, 0]);
+        machine.set_limits(Limits {
+            instructions: Some(5),
+            ..Limits::default()
+        });
+        let error = machine.run_until_io().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Instructions(5)));
+        assert_eq!((error.ip, machine.cycles()), (4, 5));
+        // the limit is on the total, so resuming stops at once until it is raised
+        assert_eq!(machine.run_until_io().unwrap_err().ip, 4);
+        machine.set_limits(Limits {
+            instructions: Some(machine.cycles() + 1),
+            ..Limits::default()
+        });
+        let error = machine.run_until_io().unwrap_err();
+        assert_eq!((error.ip, machine.cycles()), (0, 6));
+
+        machine.set_limits(Limits {
+            memory: Some(7),
+            ..Limits::default()
+        });
+        let error = machine.run_until_io().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Memory(7)));
+        assert_eq!((error.ip, machine.cycles()), (0, 6));
+        assert_eq!(machine.read(7), 2);
+    }
+}
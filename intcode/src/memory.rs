@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+
+use crate::Word;
+
+// Storage for a Machine's memory.  Every address exists; ones that have never been written to
+// read as zero.
+pub trait Memory<W: Word = isize> {
+    fn read(&self, address: usize) -> W;
+    fn write(&mut self, address: usize, value: W);
+
+    // One past the highest address that has been written to.
+    fn extent(&self) -> usize;
+
+    // Runs of memory, in ascending order of address, that cover everything that might be nonzero.
+    fn segments(&self) -> Vec<(usize, &[W])>;
+
+    // Everything up to `extent`, as one contiguous Vec.  That needs room for every address, so
+    // for a program that has written somewhere far away, see try_to_vec.
+    fn to_vec(&self) -> Vec<W> {
+        let mut result = vec![W::default(); self.extent()];
+        for (address, words) in self.segments() {
+            result[address..address + words.len()].clone_from_slice(words);
+        }
+        result
+    }
+
+    // to_vec, unless the extent is beyond MAX_VEC.
+    fn try_to_vec(&self) -> Option<Vec<W>> {
+        match self.extent() {
+            x if x > MAX_VEC => None,
+            _ => Some(self.to_vec()),
+        }
+    }
+}
+
+// The most words try_to_vec will allocate: 512MiB of isize.
+pub const MAX_VEC: usize = 1 << 26;
+
+// A plain Vec, grown to cover whatever address is written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DenseMemory<W = isize>(Vec<W>);
+
+impl<W> From<Vec<W>> for DenseMemory<W> {
+    fn from(words: Vec<W>) -> DenseMemory<W> {
+        DenseMemory(words)
+    }
+}
+
+impl<W: Word> Memory<W> for DenseMemory<W> {
+    fn read(&self, address: usize) -> W {
+        self.0.get(address).cloned().unwrap_or_default()
+    }
+
+    fn write(&mut self, address: usize, value: W) {
+        if address >= self.0.len() {
+            self.0.resize(address + 1, W::default());
+        }
+        self.0[address] = value;
+    }
+
+    fn extent(&self) -> usize {
+        self.0.len()
+    }
+
+    fn segments(&self) -> Vec<(usize, &[W])> {
+        vec![(0, &self.0[..])]
+    }
+
+    fn to_vec(&self) -> Vec<W> {
+        self.0.clone()
+    }
+}
+
+pub const PAGE_SIZE: usize = 4096;
+
+// Allocates PAGE_SIZE-word pages as they are written to, so a program can scribble on address
+// 10^12 without needing terabytes of RAM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PagedMemory<W = isize> {
+    // each page is exactly PAGE_SIZE words long
+    pages: BTreeMap<usize, Box<[W]>>,
+    extent: usize,
+}
+
+impl<W> Default for PagedMemory<W> {
+    fn default() -> PagedMemory<W> {
+        PagedMemory {
+            pages: BTreeMap::new(),
+            extent: 0,
+        }
+    }
+}
+
+impl<W: Word> From<Vec<W>> for PagedMemory<W> {
+    fn from(words: Vec<W>) -> PagedMemory<W> {
+        let mut memory = PagedMemory::default();
+        for (address, value) in words.into_iter().enumerate() {
+            memory.write(address, value);
+        }
+        memory
+    }
+}
+
+impl<W: Word> Memory<W> for PagedMemory<W> {
+    fn read(&self, address: usize) -> W {
+        match self.pages.get(&(address / PAGE_SIZE)) {
+            Some(page) => page[address % PAGE_SIZE].clone(),
+            None => W::default(),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: W) {
+        self.pages
+            .entry(address / PAGE_SIZE)
+            .or_insert_with(|| vec![W::default(); PAGE_SIZE].into_boxed_slice())
+            [address % PAGE_SIZE] = value;
+        self.extent = self.extent.max(address + 1);
+    }
+
+    fn extent(&self) -> usize {
+        self.extent
+    }
+
+    fn segments(&self) -> Vec<(usize, &[W])> {
+        self.pages
+            .iter()
+            .map(|(page, words)| {
+                let address = page * PAGE_SIZE;
+                // the last page only counts up to the extent
+                let len = (self.extent - address).min(PAGE_SIZE);
+                (address, &words[..len])
+            })
+            .collect()
+    }
+}
+
+// How far past the end of dense memory a write may land before AutoMemory gives up on growing
+// the Vec and switches to pages.
+const DENSE_GROWTH_LIMIT: usize = 1 << 20;
+
+// Dense until something writes far past the end of memory, then paged from then on.  This is what
+// Machine::new uses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AutoMemory<W = isize> {
+    Dense(DenseMemory<W>),
+    Paged(PagedMemory<W>),
+}
+
+impl<W> Default for AutoMemory<W> {
+    fn default() -> AutoMemory<W> {
+        AutoMemory::Dense(DenseMemory(Vec::new()))
+    }
+}
+
+impl<W> From<Vec<W>> for AutoMemory<W> {
+    fn from(words: Vec<W>) -> AutoMemory<W> {
+        AutoMemory::Dense(words.into())
+    }
+}
+
+impl<W: Word> Memory<W> for AutoMemory<W> {
+    fn read(&self, address: usize) -> W {
+        match self {
+            AutoMemory::Dense(m) => m.read(address),
+            AutoMemory::Paged(m) => m.read(address),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: W) {
+        if let AutoMemory::Dense(m) = self {
+            if address >= m.extent().saturating_add(DENSE_GROWTH_LIMIT) {
+                let mut paged = PagedMemory::default();
+                for (idx, value) in m.0.iter().enumerate() {
+                    if !value.is_zero() {
+                        paged.write(idx, value.clone());
+                    }
+                }
+                paged.extent = m.extent();
+                *self = AutoMemory::Paged(paged);
+            }
+        }
+
+        match self {
+            AutoMemory::Dense(m) => m.write(address, value),
+            AutoMemory::Paged(m) => m.write(address, value),
+        }
+    }
+
+    fn extent(&self) -> usize {
+        match self {
+            AutoMemory::Dense(m) => m.extent(),
+            AutoMemory::Paged(m) => m.extent(),
+        }
+    }
+
+    fn segments(&self) -> Vec<(usize, &[W])> {
+        match self {
+            AutoMemory::Dense(m) => m.segments(),
+            AutoMemory::Paged(m) => m.segments(),
+        }
+    }
+
+    fn to_vec(&self) -> Vec<W> {
+        match self {
+            AutoMemory::Dense(m) => m.to_vec(),
+            AutoMemory::Paged(m) => m.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AutoMemory, DenseMemory, Memory, PagedMemory, PAGE_SIZE};
+
+    fn exercise<M: Memory>(mut memory: M) {
+        assert_eq!(memory.read(1), 2);
+        assert_eq!(memory.read(100_000), 0);
+        memory.write(PAGE_SIZE + 1, 7);
+        assert_eq!(memory.read(PAGE_SIZE + 1), 7);
+        assert_eq!(memory.extent(), PAGE_SIZE + 2);
+
+        let words = memory.to_vec();
+        assert_eq!(words.len(), PAGE_SIZE + 2);
+        assert_eq!(&words[..3], &[1, 2, 3]);
+        assert_eq!(words[PAGE_SIZE + 1], 7);
+    }
+
+    #[test]
+    fn backends() {
+        exercise(DenseMemory::from(vec![1, 2, 3]));
+        exercise(PagedMemory::from(vec![1, 2, 3]));
+        exercise(AutoMemory::from(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn paged_segments() {
+        let mut memory = PagedMemory::from(vec![1isize, 2, 3]);
+        memory.write(1_000_000_000_000, 5);
+        assert_eq!(memory.read(1_000_000_000_000), 5);
+        let segments = memory.segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].0, 0);
+        assert_eq!(segments[0].1.len(), PAGE_SIZE);
+        assert_eq!(segments[1].0 + segments[1].1.len(), 1_000_000_000_001);
+    }
+
+    #[test]
+    fn auto_switches_to_pages() {
+        let mut memory = AutoMemory::from(vec![1isize, 2, 3]);
+        memory.write(1000, 4);
+        assert!(matches!(memory, AutoMemory::Dense(_)));
+
+        memory.write(1_000_000_000_000, 5);
+        assert!(matches!(memory, AutoMemory::Paged(_)));
+        assert_eq!(memory.read(2), 3);
+        assert_eq!(memory.read(1000), 4);
+        assert_eq!(memory.read(1_000_000_000_000), 5);
+        assert_eq!(memory.extent(), 1_000_000_000_001);
+    }
+}
@@ -0,0 +1,315 @@
+// Several machines wired together, run round-robin until they all halt or stop making progress.
+//
+//   - Pipeline: each machine's output is the next one's input, and the last one's output is the
+//     network's output.
+//   - Ring: a pipeline whose last machine also feeds the first, like day 7's feedback loop.
+//   - Packets: every machine's output is a stream of (destination, x, y) triples.  x and y are
+//     delivered to the machine with that index, or to the network's outbox if there is no such
+//     machine.  A machine that asks for input when none is queued is given -1, as on day 23.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::{Error, Machine};
+
+// What a machine in a packet network reads when nothing has been sent to it.
+pub const NO_PACKET: isize = -1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    Pipeline,
+    Ring,
+    Packets,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub destination: isize,
+    pub x: isize,
+    pub y: isize,
+}
+
+// Why `Network::run` returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    // every machine has halted
+    Halted,
+    // packet networks only: a round went by in which every machine was given NO_PACKET and
+    // nobody sent anything
+    Idle,
+    // packet networks only: a packet was addressed outside the network, and is in the outbox
+    Sent,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkError {
+    Fault { machine: usize, error: Error },
+    // every machine that has not halted is waiting for input that nothing is going to send
+    Deadlock { waiting: Vec<usize> },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Fault { machine, error } => write!(f, "machine {}: {}", machine, error),
+            NetworkError::Deadlock { waiting } => {
+                let waiting: Vec<String> = waiting.iter().map(|x| x.to_string()).collect();
+                write!(
+                    f,
+                    "deadlock: machine(s) {} waiting for input",
+                    waiting.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+pub struct Network {
+    machines: Vec<Machine>,
+    topology: Topology,
+    output: Vec<isize>,
+    outbox: VecDeque<Packet>,
+    // output that is not yet a whole packet, per machine
+    partial: Vec<Vec<isize>>,
+}
+
+impl Network {
+    pub fn new(machines: Vec<Machine>, topology: Topology) -> Network {
+        let partial = vec![Vec::new(); machines.len()];
+        Network {
+            machines,
+            topology,
+            output: Vec::new(),
+            outbox: VecDeque::new(),
+            partial,
+        }
+    }
+
+    // `count` copies of one program, each started with the matching entry of `inputs` (if any)
+    // queued, e.g. day 7's phase settings or day 23's network addresses.
+    pub fn from_program(
+        program: &[isize],
+        count: usize,
+        inputs: &[&[isize]],
+        topology: Topology,
+    ) -> Network {
+        let machines = (0..count)
+            .map(|i| {
+                let mut machine = Machine::new(program.to_vec());
+                if let Some(input) = inputs.get(i) {
+                    machine.extend_input(input.iter().cloned());
+                }
+                machine
+            })
+            .collect();
+        Network::new(machines, topology)
+    }
+
+    pub fn machines(&self) -> &[Machine] {
+        &self.machines
+    }
+
+    pub fn machine_mut(&mut self, idx: usize) -> &mut Machine {
+        &mut self.machines[idx]
+    }
+
+    pub fn push_input(&mut self, machine: usize, value: isize) {
+        self.machines[machine].push_input(value);
+    }
+
+    pub fn send(&mut self, packet: Packet) {
+        self.route(packet);
+    }
+
+    // Everything the last machine of a pipeline or ring has output so far.
+    pub fn take_output(&mut self) -> Vec<isize> {
+        std::mem::take(&mut self.output)
+    }
+
+    pub fn take_packets(&mut self) -> Vec<Packet> {
+        self.outbox.drain(..).collect()
+    }
+
+    pub fn run(&mut self) -> Result<Stop, NetworkError> {
+        loop {
+            let mut progress = false;
+            let mut idle = true;
+            for idx in 0..self.machines.len() {
+                let machine = &mut self.machines[idx];
+                if machine.is_halted() {
+                    continue;
+                }
+                let starved = machine.pending_input().is_empty();
+                if starved && self.topology == Topology::Packets {
+                    machine.push_input(NO_PACKET);
+                }
+
+                let cycles = machine.cycles();
+                machine
+                    .run_until_blocked()
+                    .map_err(|error| NetworkError::Fault {
+                        machine: idx,
+                        error,
+                    })?;
+                progress |= machine.cycles() != cycles;
+
+                let output = machine.take_output();
+                idle &= starved && output.is_empty();
+                self.deliver(idx, output);
+            }
+
+            if self.machines.iter().all(|m| m.is_halted()) {
+                return Ok(Stop::Halted);
+            }
+            match self.topology {
+                Topology::Packets if !self.outbox.is_empty() => return Ok(Stop::Sent),
+                Topology::Packets if idle => return Ok(Stop::Idle),
+                Topology::Pipeline | Topology::Ring if !progress => {
+                    let waiting = (0..self.machines.len())
+                        .filter(|&i| !self.machines[i].is_halted())
+                        .collect();
+                    return Err(NetworkError::Deadlock { waiting });
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn deliver(&mut self, from: usize, output: Vec<isize>) {
+        let last = self.machines.len() - 1;
+        match self.topology {
+            Topology::Pipeline | Topology::Ring if from < last => {
+                self.machines[from + 1].extend_input(output)
+            }
+            Topology::Pipeline => self.output.extend(output),
+            Topology::Ring => {
+                self.machines[0].extend_input(output.iter().cloned());
+                self.output.extend(output);
+            }
+            Topology::Packets => {
+                self.partial[from].extend(output);
+                let words = std::mem::take(&mut self.partial[from]);
+                let mut chunks = words.chunks_exact(3);
+                for chunk in &mut chunks {
+                    self.route(Packet {
+                        destination: chunk[0],
+                        x: chunk[1],
+                        y: chunk[2],
+                    });
+                }
+                self.partial[from] = chunks.remainder().to_vec();
+            }
+        }
+    }
+
+    fn route(&mut self, packet: Packet) {
+        if packet.destination >= 0 && (packet.destination as usize) < self.machines.len() {
+            self.machines[packet.destination as usize].extend_input(vec![packet.x, packet.y]);
+        } else {
+            self.outbox.push_back(packet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Network, NetworkError, Packet, Stop, Topology};
+
+    #[test]
+    fn pipeline() {
+        // adds its phase setting to its input
+        let program = [3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99, 0, 0];
+        let mut network =
+            Network::from_program(&program, 3, &[&[1], &[10], &[100]], Topology::Pipeline);
+        network.push_input(0, 1000);
+        assert_eq!(network.run(), Ok(Stop::Halted));
+        assert_eq!(network.take_output(), vec![1111]);
+    }
+
+    #[test]
+    fn ring_deadlock() {
+        // both machines want input before they will output anything
+        let program = [3, 7, 4, 7, 1105, 1, 0, 0];
+        let mut network = Network::from_program(&program, 2, &[], Topology::Ring);
+        assert_eq!(
+            network.run(),
+            Err(NetworkError::Deadlock {
+                waiting: vec![0, 1]
+            })
+        );
+
+        // with one value circulating it goes forever, so stop it with a fault instead
+        network.push_input(0, -5);
+        network.machine_mut(1).write(4, 42);
+        match network.run() {
+            Err(NetworkError::Fault { machine: 1, .. }) => (),
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+
+    #[test]
+    fn packets() {
+        // reads its address, sends (address + 1, address, address) and then forwards whatever
+        // it receives to 255
+        let program = crate::asm::assemble(
+            "
+                    in [address]
+                    add [address], #1, [next]
+                    out [next]
+                    out [address]
+                    out [address]
+            loop:   in [x]
+                    eq [x], #-1, [t]
+                    jnz [t], #loop
+                    in [y]
+                    out #255
+                    out [x]
+                    out [y]
+                    jz #0, #loop
+            address: .data 0
+            next:   .data 0
+            x:      .data 0
+            y:      .data 0
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut network = Network::from_program(&program, 2, &[&[0], &[1]], Topology::Packets);
+        // machine 0's packet reaches machine 1 and is forwarded within the same round, and
+        // machine 1's own packet goes to 2, which is outside the network
+        assert_eq!(network.run(), Ok(Stop::Sent));
+        assert_eq!(
+            network.take_packets(),
+            vec![
+                Packet {
+                    destination: 2,
+                    x: 1,
+                    y: 1
+                },
+                Packet {
+                    destination: 255,
+                    x: 0,
+                    y: 0
+                }
+            ]
+        );
+        assert_eq!(network.run(), Ok(Stop::Idle));
+
+        network.send(Packet {
+            destination: 1,
+            x: 7,
+            y: 8,
+        });
+        assert_eq!(network.run(), Ok(Stop::Sent));
+        assert_eq!(
+            network.take_packets(),
+            vec![Packet {
+                destination: 255,
+                x: 7,
+                y: 8
+            }]
+        );
+    }
+}
@@ -0,0 +1,264 @@
+// Extra opcodes for a Machine, for experimenting with dialects of intcode or adding
+// instrumentation like a debug print or an assertion:
+//
+//     let mut opcodes = Opcodes::new();
+//     // 42: assert that the first parameter is non-zero
+//     opcodes.register(42, 1, |operands| match operands.read(1)? {
+//         0 => Err(ErrorKind::Custom("assertion failed".to_string())),
+//         _ => Ok(Action::Next),
+//     });
+//     machine.set_opcodes(opcodes);
+//
+// Registered opcodes are looked up before the built-in ones, so they can also replace them.  Any
+// other opcode that the machine does not know goes to the fallback handler if there is one, and
+// is an ErrorKind::UnknownOpcode otherwise.
+//
+// Only Machine supports extra opcodes; FastMachine, the transpiler and the disassembler know
+// nothing of them.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::memory::{AutoMemory, Memory};
+use crate::{ErrorKind, Machine, Observer, Word};
+
+// What the machine does once a handler returns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action<W = isize> {
+    // Go on to the next instruction, after this one's parameters.  The fallback handler has no
+    // arity to go by, so for it this is the very next word.
+    Next,
+    Jump(usize),
+    // Output a value, then go on as for Next
+    Output(W),
+    // Stop, as if an input instruction found no input queued.  The instruction will be run again
+    // once there is input, so a handler should return this before it changes anything.
+    NeedsInput,
+    Halt,
+}
+
+pub type Handler<W, M> =
+    Arc<dyn Fn(&mut Operands<'_, W, M>) -> Result<Action<W>, ErrorKind<W>> + Send + Sync>;
+
+pub struct Opcodes<W = isize, M = AutoMemory<W>> {
+    // opcode -> (arity, handler)
+    handlers: BTreeMap<isize, (usize, Handler<W, M>)>,
+    fallback: Option<Handler<W, M>>,
+}
+
+const BUILTIN: [isize; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+impl<W: Word, M: Memory<W>> Opcodes<W, M> {
+    pub fn new() -> Opcodes<W, M> {
+        Opcodes {
+            handlers: BTreeMap::new(),
+            fallback: None,
+        }
+    }
+
+    // `arity` is how many parameters follow the instruction; operands may only be read or
+    // written within it.  The opcode is the last two digits of an instruction, so it must be in
+    // 0..100; it may be one of the built-in ones, which this then replaces.
+    pub fn register<F>(&mut self, opcode: isize, arity: usize, handler: F)
+    where
+        F: Fn(&mut Operands<'_, W, M>) -> Result<Action<W>, ErrorKind<W>> + Send + Sync + 'static,
+    {
+        assert!(
+            (0..100).contains(&opcode),
+            "opcode {} is not two digits; an instruction could never have it",
+            opcode
+        );
+        self.handlers.insert(opcode, (arity, Arc::new(handler)));
+    }
+
+    pub fn set_fallback<F>(&mut self, handler: F)
+    where
+        F: Fn(&mut Operands<'_, W, M>) -> Result<Action<W>, ErrorKind<W>> + Send + Sync + 'static,
+    {
+        self.fallback = Some(Arc::new(handler));
+    }
+
+    // The handler for an opcode and its arity, or None if the built-in instruction (or an
+    // UnknownOpcode fault) should happen.
+    pub(crate) fn lookup(&self, opcode: isize) -> Option<(Option<usize>, Handler<W, M>)> {
+        match self.handlers.get(&opcode) {
+            Some((arity, handler)) => Some((Some(*arity), handler.clone())),
+            None if BUILTIN.contains(&opcode) => None,
+            None => self.fallback.clone().map(|handler| (None, handler)),
+        }
+    }
+}
+
+impl<W: Word, M: Memory<W>> Default for Opcodes<W, M> {
+    fn default() -> Opcodes<W, M> {
+        Opcodes::new()
+    }
+}
+
+impl<W, M> fmt::Debug for Opcodes<W, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Opcodes")
+            .field("opcodes", &self.handlers.keys().collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+// A handler's view of the machine, while it executes one instruction.  Reads and writes through
+// here are reported to the machine's observer like those of built-in instructions.
+pub struct Operands<'a, W, M> {
+    pub(crate) machine: &'a mut Machine<W, M>,
+    pub(crate) observer: &'a mut dyn Observer<W>,
+    pub(crate) opcode: isize,
+    pub(crate) arity: Option<usize>,
+}
+
+impl<W: Word, M: Memory<W>> Operands<'_, W, M> {
+    pub fn opcode(&self) -> isize {
+        self.opcode
+    }
+
+    pub fn ip(&self) -> usize {
+        self.machine.ip()
+    }
+
+    pub fn relative_base(&self) -> W {
+        self.machine.relative_base()
+    }
+
+    // The value of a parameter (1-based, as in ErrorKind::InvalidMode), in whatever mode the
+    // instruction gives it.
+    pub fn read(&mut self, parameter: usize) -> Result<W, ErrorKind<W>> {
+        self.check(parameter)?;
+        self.machine
+            .get_read_operand_at(parameter, &mut *self.observer)
+    }
+
+    // Stores to the address a parameter names, which must be in position or relative mode.
+    pub fn write(&mut self, parameter: usize, value: W) -> Result<(), ErrorKind<W>> {
+        self.check(parameter)?;
+        let address = self
+            .machine
+            .get_write_index_at(parameter, &mut *self.observer)?;
+        self.machine.store(address, value, &mut *self.observer);
+        Ok(())
+    }
+
+    pub fn read_memory(&self, address: usize) -> W {
+        self.machine.read(address)
+    }
+
+    pub fn write_memory(&mut self, address: usize, value: W) {
+        self.machine.store(address, value, &mut *self.observer);
+    }
+
+    // The input still queued, for a handler that needs more than one value to check that they
+    // are all there before taking any.
+    pub fn pending_input(&self) -> &VecDeque<W> {
+        self.machine.pending_input()
+    }
+
+    // The next queued input, if any; see Action::NeedsInput.
+    pub fn input(&mut self) -> Option<W> {
+        self.machine.pop_input(&mut *self.observer)
+    }
+
+    fn check(&self, parameter: usize) -> Result<(), ErrorKind<W>> {
+        match self.arity {
+            Some(arity) if parameter == 0 || parameter > arity => Err(ErrorKind::Custom(format!(
+                "opcode {} has no parameter {}",
+                self.opcode, parameter
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Action, Opcodes};
+    use crate::{ErrorKind, Machine, State};
+
+    #[test]
+    fn extra_opcodes() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let log = printed.clone();
+        let mut opcodes = Opcodes::new();
+        // 20: debug print
+        opcodes.register(20, 1, move |operands| {
+            log.lock().unwrap().push(operands.read(1)?);
+            Ok(Action::Next)
+        });
+        // 21: assert non-zero
+        opcodes.register(21, 1, |operands| match operands.read(1)? {
+            0 => Err(ErrorKind::Custom("assertion failed".to_string())),
+            _ => Ok(Action::Next),
+        });
+        // 22: subtract
+        opcodes.register(22, 3, |operands| {
+            let value = operands.read(1)? - operands.read(2)?;
+            operands.write(3, value)?;
+            Ok(Action::Next)
+        });
+
+        let mut machine = Machine::new(vec![
+            120, 5, // debug print 5
+            22, 12, 13, 14, // [14] = [12] - [13]
+            1121, 1, // assert 1
+            21, 14, // assert [14]
+            99, 0, // halt, never reached
+            10, 10, 7,
+        ]);
+        machine.set_opcodes(opcodes);
+        let error = machine.run_until_blocked().unwrap_err();
+        assert_eq!(error.ip, 8);
+        assert_eq!(
+            error.kind,
+            ErrorKind::Custom("assertion failed".to_string())
+        );
+        assert_eq!(machine.read(14), 0);
+        assert_eq!(*printed.lock().unwrap(), vec![5]);
+        assert_eq!(machine.cycles(), 3);
+
+        // an opcode that does not fit in an instruction's last two digits could never run
+        let result = std::panic::catch_unwind(|| {
+            let mut opcodes: Opcodes = Opcodes::new();
+            opcodes.register(150, 0, |_| Ok(Action::Next));
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn fallback() {
+        let mut opcodes = Opcodes::new();
+        // unknown instructions are output and skipped; 4 (output) is replaced with a halt
+        opcodes.set_fallback(|operands| Ok(Action::Output(operands.read_memory(operands.ip()))));
+        opcodes.register(4, 1, |_| Ok(Action::Halt));
+
+        let program = vec![50, 1001, 8, 3, 8, 4, 8, 0, 12];
+        let mut machine = Machine::new(program.clone());
+        machine.set_opcodes(opcodes);
+        assert_eq!(machine.run_until_blocked(), Ok(State::Halted));
+        assert_eq!(machine.take_output(), vec![50]);
+        assert_eq!(machine.read(8), 15);
+
+        // a fallback has no arity, so it may read parameters that have no mode digit
+        let mut opcodes = Opcodes::new();
+        opcodes.set_fallback(|operands| Ok(Action::Output(operands.read(20)?)));
+        let mut machine = Machine::new(vec![50]);
+        machine.write(20, 24);
+        machine.write(24, 7);
+        machine.set_opcodes(opcodes);
+        assert_eq!(machine.run_until_io(), Ok(State::Output(7)));
+
+        // without the registry, the same program faults
+        let mut machine = Machine::new(program);
+        assert_eq!(
+            machine.run_until_blocked().unwrap_err().kind,
+            ErrorKind::UnknownOpcode(50)
+        );
+    }
+}
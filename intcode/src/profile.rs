@@ -0,0 +1,166 @@
+// Counts how often each address and each opcode is executed.
+//
+//     let mut profile = Profile::new();
+//     machine.run_until_blocked_with(&mut profile)?;
+//     print!("{}", profile.report(machine.memory(), 10));
+//
+// Only instructions that retire are counted, so the totals match Machine::cycles for a run that
+// started from a fresh machine.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use crate::instruction::{Instruction, Opcode};
+use crate::{Memory, Observer};
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    by_address: BTreeMap<usize, u64>,
+    by_opcode: BTreeMap<isize, u64>,
+    // the opcode last executed at each address, for the CSV
+    opcodes: BTreeMap<usize, isize>,
+    total: u64,
+    pending: Option<(usize, isize)>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn by_address(&self) -> &BTreeMap<usize, u64> {
+        &self.by_address
+    }
+
+    pub fn by_opcode(&self) -> &BTreeMap<isize, u64> {
+        &self.by_opcode
+    }
+
+    // The `n` most-executed addresses, busiest first.
+    pub fn hot_spots(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut spots: Vec<(usize, u64)> = self.by_address.iter().map(|(&a, &c)| (a, c)).collect();
+        spots.sort_by_key(|&(address, count)| (std::cmp::Reverse(count), address));
+        spots.truncate(n);
+        spots
+    }
+
+    // A human-readable summary: the `top` hottest addresses, disassembled from `memory`, followed
+    // by the counts for each opcode.
+    pub fn report<M: Memory>(&self, memory: &M, top: usize) -> String {
+        let mut report = format!("{} instructions retired\n\n", self.total);
+        report += "     count       %  instruction\n";
+        for (address, count) in self.hot_spots(top) {
+            let window: Vec<isize> = (address..address + 4).map(|a| memory.read(a)).collect();
+            let text = match Instruction::decode(&window, 0) {
+                Some(i) => i.to_string(),
+                None => format!(".data {}", window[0]),
+            };
+            report += &format!(
+                "{:>10} {:>6.2}%  {:>6}: {}\n",
+                count,
+                self.percent(count),
+                address,
+                text
+            );
+        }
+
+        report += "\n     count       %  opcode\n";
+        let mut opcodes: Vec<(isize, u64)> = self.by_opcode.iter().map(|(&o, &c)| (o, c)).collect();
+        opcodes.sort_by_key(|&(opcode, count)| (std::cmp::Reverse(count), opcode));
+        for (opcode, count) in opcodes {
+            let name = Opcode::from_code(opcode)
+                .map(|o| o.mnemonic().to_string())
+                .unwrap_or_else(|| opcode.to_string());
+            report += &format!("{:>10} {:>6.2}%  {}\n", count, self.percent(count), name);
+        }
+        report
+    }
+
+    // One row per executed address, in address order: `address,opcode,count`.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "address,opcode,count\n".to_string();
+        for (address, count) in &self.by_address {
+            csv += &format!("{},{},{}\n", address, self.opcodes[address], count);
+        }
+        csv
+    }
+
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::File::create(path)?.write_all(self.to_csv().as_bytes())
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        100.0 * count as f64 / self.total.max(1) as f64
+    }
+}
+
+impl Observer for Profile {
+    fn begin(&mut self, ip: usize, &instruction: &isize) {
+        self.pending = Some((ip, instruction % 100));
+    }
+
+    fn retire(&mut self, _next_ip: usize) {
+        if let Some((ip, opcode)) = self.pending.take() {
+            *self.by_address.entry(ip).or_insert(0) += 1;
+            *self.by_opcode.entry(opcode).or_insert(0) += 1;
+            self.opcodes.insert(ip, opcode);
+            self.total += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profile;
+    use crate::Machine;
+
+    #[test]
+    fn counts() {
+        // counts down from 3, printing each value
+        let program = crate::asm::assemble(
+            "
+            loop:   out [n]
+                    add [n], #-1, [n]
+                    jnz [n], #loop
+                    hlt
+            n:      .data 3
+            ",
+        )
+        .unwrap();
+        let mut machine = Machine::new(program);
+        let mut profile = Profile::new();
+        machine.run_until_blocked_with(&mut profile).unwrap();
+
+        assert_eq!(profile.total(), 10);
+        assert_eq!(profile.total(), machine.cycles());
+        assert_eq!(profile.hot_spots(2), vec![(0, 3), (2, 3)]);
+        assert_eq!(profile.by_opcode()[&99], 1);
+        assert_eq!(
+            profile.to_csv(),
+            "address,opcode,count\n0,4,3\n2,1,3\n6,5,3\n9,99,1\n"
+        );
+
+        let report = profile.report(machine.memory(), 1);
+        assert!(report.starts_with("10 instructions retired\n"));
+        assert!(report.contains("         3  30.00%       0: out [10]\n"));
+        assert!(report.contains("         1  10.00%  hlt\n"));
+    }
+
+    #[test]
+    fn day_09() {
+        let program =
+            crate::parse_opcodes(&std::fs::read_to_string("../inputs/day_09.txt").unwrap());
+        let mut machine = Machine::new(program);
+        machine.push_input(1);
+        let mut profile = Profile::new();
+        machine.run_until_blocked_with(&mut profile).unwrap();
+        assert_eq!(profile.total(), machine.cycles());
+        assert_eq!(profile.by_address().values().sum::<u64>(), profile.total());
+        assert_eq!(profile.by_opcode().values().sum::<u64>(), profile.total());
+    }
+}
@@ -0,0 +1,307 @@
+// Watches a program for self-modifying code, and works out which parts of memory it used as code
+// and which as data:
+//
+//     let mut map = CodeMap::new();
+//     machine.run_until_blocked_with(&mut map)?;
+//     for warning in map.warnings() {
+//         eprintln!("{}", warning);
+//     }
+//     print!("{}", map.report());
+//
+// Every word of an instruction that ran (the opcode and its parameters) counts as code.  A write
+// to code gives a warning, as does a write to the instruction that runs straight afterwards, even
+// if it never ran before: that is how day 5 turns its input into an instruction.
+//
+// The second kind is only noticed once the next instruction begins, so a write made just before
+// a run stops is not checked until the machine carries on.  A CodeMap attached partway through a
+// run knows nothing of the code that already ran, and takes the relative base to be 0.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::instruction::{Mode, Opcode};
+use crate::Observer;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    // the address has already run as part of an instruction
+    Executed,
+    // the address is part of the instruction that runs next
+    Next,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    // the instruction that made the write
+    pub ip: usize,
+    pub address: usize,
+    pub old: isize,
+    pub new: isize,
+    pub kind: Kind,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.kind {
+            Kind::Executed => "code that has already run",
+            Kind::Next => "the next instruction",
+        };
+        write!(
+            f,
+            "instruction at {} changed {} from {} to {}, in {}",
+            self.ip, self.address, self.old, self.new, what
+        )
+    }
+}
+
+// How a run used an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Use {
+    Code,
+    Data,
+    // ran as code and was also read or written as data
+    Both,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CodeMap {
+    code: BTreeSet<usize>,
+    data: BTreeSet<usize>,
+    relative_base: isize,
+    warnings: Vec<Warning>,
+    // the instruction being executed, until it retires
+    current: Option<Step>,
+    // the writes the last instruction made, until the next one shows whether they land on it
+    pending: Vec<Warning>,
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    ip: usize,
+    opcode: isize,
+    parameters: usize,
+}
+
+impl CodeMap {
+    pub fn new() -> CodeMap {
+        CodeMap::default()
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // Whether any instruction has run from `address` or had a parameter there.
+    pub fn is_code(&self, address: usize) -> bool {
+        self.code.contains(&address)
+    }
+
+    pub fn code(&self) -> &BTreeSet<usize> {
+        &self.code
+    }
+
+    // Addresses that were read or written as operands.
+    pub fn data(&self) -> &BTreeSet<usize> {
+        &self.data
+    }
+
+    // Every address the run touched, grouped into runs of the same use.
+    pub fn split(&self) -> Vec<(RangeInclusive<usize>, Use)> {
+        let mut uses = BTreeMap::new();
+        for &address in &self.code {
+            uses.insert(address, Use::Code);
+        }
+        for &address in &self.data {
+            let use_ = match uses.get(&address) {
+                Some(Use::Code) | Some(Use::Both) => Use::Both,
+                _ => Use::Data,
+            };
+            uses.insert(address, use_);
+        }
+
+        let mut split: Vec<(RangeInclusive<usize>, Use)> = Vec::new();
+        for (address, use_) in uses {
+            match split.last_mut() {
+                Some((range, last)) if *last == use_ && *range.end() + 1 == address => {
+                    *range = *range.start()..=address
+                }
+                _ => split.push((address..=address, use_)),
+            }
+        }
+        split
+    }
+
+    // The split, one range per line, followed by the warnings.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for (range, use_) in self.split() {
+            let name = match use_ {
+                Use::Code => "code",
+                Use::Data => "data",
+                Use::Both => "code and data",
+            };
+            report += &format!(
+                "{:>6}..={:<6} {:>5} words  {}\n",
+                range.start(),
+                range.end(),
+                range.end() - range.start() + 1,
+                name
+            );
+        }
+        if !self.warnings.is_empty() {
+            report += &format!("\n{} self-modifying write(s):\n", self.warnings.len());
+            for warning in &self.warnings {
+                report += &format!("{}\n", warning);
+            }
+        }
+        report
+    }
+}
+
+impl Observer for CodeMap {
+    fn begin(&mut self, ip: usize, &instruction: &isize) {
+        let arity = Opcode::from_code(instruction % 100).map_or(0, |o| o.arity());
+        for mut warning in self.pending.drain(..) {
+            if (ip..=ip + arity).contains(&warning.address) {
+                warning.kind = Kind::Next;
+                self.warnings.push(warning);
+            } else if self.code.contains(&warning.address) {
+                self.warnings.push(warning);
+            }
+        }
+        self.current = Some(Step {
+            ip,
+            opcode: instruction % 100,
+            parameters: 0,
+        });
+    }
+
+    fn operand(&mut self, mode: Mode, &parameter: &isize, _value: &isize) {
+        if let Some(step) = &mut self.current {
+            let idx = step.parameters;
+            step.parameters += 1;
+            // writes are recorded by write(), with the address they actually land on
+            let writes = Opcode::from_code(step.opcode).is_some_and(|o| o.writes(idx));
+            let address = match mode {
+                Mode::Immediate => return,
+                Mode::Position => parameter,
+                Mode::Relative => parameter.wrapping_add(self.relative_base),
+            };
+            if !writes && address >= 0 {
+                self.data.insert(address as usize);
+            }
+        }
+    }
+
+    fn write(&mut self, address: usize, &old: &isize, &new: &isize) {
+        self.data.insert(address);
+        if let Some(step) = &self.current {
+            self.pending.push(Warning {
+                ip: step.ip,
+                address,
+                old,
+                new,
+                kind: Kind::Executed,
+            });
+        }
+    }
+
+    fn relative_base(&mut self, _old: &isize, &new: &isize) {
+        self.relative_base = new;
+    }
+
+    fn retire(&mut self, _next_ip: usize) {
+        if let Some(step) = self.current.take() {
+            self.code.extend(step.ip..=step.ip + step.parameters);
+            // an instruction that overwrote itself counts as having already run
+            for warning in &self.pending {
+                if (step.ip..=step.ip + step.parameters).contains(&warning.address) {
+                    self.warnings.push(warning.clone());
+                }
+            }
+            self.pending
+                .retain(|w| !(step.ip..=step.ip + step.parameters).contains(&w.address));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CodeMap, Kind, Use, Warning};
+    use crate::Machine;
+
+    #[test]
+    fn loop_rewrite() {
+        // counts down by rewriting the immediate operand of its own output instruction
+        let program = crate::asm::assemble(
+            "
+            loop:   out #3
+                    add [loop+1], #-1, [loop+1]
+                    jnz [loop+1], #loop
+                    hlt
+            ",
+        )
+        .unwrap();
+        let mut machine = Machine::new(program);
+        let mut map = CodeMap::new();
+        machine.run_until_blocked_with(&mut map).unwrap();
+        assert_eq!(machine.take_output(), vec![3, 2, 1]);
+
+        assert_eq!(
+            map.warnings()[0],
+            Warning {
+                ip: 2,
+                address: 1,
+                old: 3,
+                new: 2,
+                kind: Kind::Executed
+            }
+        );
+        assert_eq!(map.warnings().len(), 3);
+        assert_eq!(
+            map.split(),
+            vec![(0..=0, Use::Code), (1..=1, Use::Both), (2..=9, Use::Code)]
+        );
+    }
+
+    #[test]
+    fn day_05() {
+        let program = crate::parse_opcodes(
+            &std::fs::read_to_string("../inputs/day_05.txt").expect("could not read input"),
+        );
+
+        // the input is added to the word at 6, which then runs as an add or as a jump
+        let mut maps = Vec::new();
+        for &(input, new) in &[(1, 1101), (5, 1105)] {
+            let mut machine = Machine::new(program.clone());
+            machine.push_input(input);
+            let mut map = CodeMap::new();
+            machine.run_until_blocked_with(&mut map).unwrap();
+            assert_eq!(
+                map.warnings()[0],
+                Warning {
+                    ip: 2,
+                    address: 6,
+                    old: 1100,
+                    new,
+                    kind: Kind::Next
+                }
+            );
+            maps.push(map);
+        }
+
+        // part 1 is straight-line code followed by its data
+        assert_eq!(maps[0].warnings().len(), 1);
+        assert_eq!(maps[0].split().last(), Some(&(223..=225, Use::Data)));
+        // part 2 also uses address 0, where the first instruction was, as a jump target
+        assert_eq!(maps[1].warnings().len(), 3);
+        assert!(maps[1].report().contains(
+            "instruction at 284 changed 0 from 3 to 294, in code that has already run\n"
+        ));
+    }
+}
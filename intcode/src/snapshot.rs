@@ -0,0 +1,368 @@
+// Saves a paused Machine to text and restores it again.  The format is line-oriented:
+//
+//     intcode-snapshot 2
+//     ip 4
+//     relative_base 0
+//     cycles 1
+//     halted false
+//     arithmetic wrapping
+//     instruction_limit 1000
+//     input 1,2
+//     memory 0 1,9,10,3,2,3,11,0,99,30,40,50
+//
+// There may be any number of `memory <address> <words>` lines; memory not covered by one of them
+// is zero.  The limit lines, `instruction_limit` and `memory_limit`, only appear for limits that
+// are set.  Version 1 snapshots, which stop after the relative base and input, are still read;
+// what they leave out starts from its default, as for a new machine.  Output that the machine has
+// buffered and any extra opcodes are not part of the snapshot.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::{Arithmetic, Limits, Machine, Memory, Word};
+
+const HEADER: &str = "intcode-snapshot 2";
+const HEADER_V1: &str = "intcode-snapshot 1";
+// lines that version 1 did not have
+const V2_ONLY: &[&str] = &[
+    "cycles",
+    "halted",
+    "arithmetic",
+    "instruction_limit",
+    "memory_limit",
+];
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "could not access snapshot: {}", e),
+            SnapshotError::Parse { line, message } => {
+                write!(f, "invalid snapshot at line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> SnapshotError {
+        SnapshotError::Io(e)
+    }
+}
+
+impl<W: Word, M: Memory<W>> Machine<W, M> {
+    pub fn to_snapshot(&self) -> String {
+        let mut snapshot = format!(
+            "{}\nip {}\nrelative_base {}\ncycles {}\nhalted {}\narithmetic {}\n",
+            HEADER,
+            self.ip(),
+            self.relative_base(),
+            self.cycles(),
+            self.is_halted(),
+            match self.arithmetic() {
+                Arithmetic::Wrapping => "wrapping",
+                Arithmetic::Checked => "checked",
+            },
+        );
+        let Limits {
+            instructions,
+            memory,
+        } = self.limits();
+        if let Some(limit) = instructions {
+            snapshot += &format!("instruction_limit {}\n", limit);
+        }
+        if let Some(limit) = memory {
+            snapshot += &format!("memory_limit {}\n", limit);
+        }
+        snapshot += &format!(
+            "input {}\n",
+            crate::format_opcodes(&self.pending_input().iter().cloned().collect::<Vec<_>>())
+        );
+        for (address, words) in self.memory().segments() {
+            snapshot += &format!("memory {} {}\n", address, crate::format_opcodes(words));
+        }
+        snapshot
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        Ok(std::fs::write(path, self.to_snapshot())?)
+    }
+}
+
+// Restores into any memory backend, e.g. `Machine::<BigInt, PagedMemory<BigInt>>::load(path)`,
+// or `<Machine>::load(path)` for the defaults.
+impl<W: Word, M: Memory<W> + Default> Machine<W, M> {
+    pub fn from_snapshot(snapshot: &str) -> Result<Machine<W, M>, SnapshotError> {
+        let mut lines = snapshot.lines().enumerate();
+        let version = match lines.next() {
+            Some((_, HEADER)) => 2,
+            Some((_, HEADER_V1)) => 1,
+            _ => {
+                return Err(SnapshotError::Parse {
+                    line: 1,
+                    message: format!("expected {:?}", HEADER),
+                })
+            }
+        };
+
+        let mut machine = Machine::with_memory(M::default());
+        for (idx, line) in lines {
+            let error = |message: String| SnapshotError::Parse {
+                line: idx + 1,
+                message,
+            };
+            let mut words = line.split_whitespace();
+            let key = words.next();
+            if let Some(key) = key.filter(|k| version < 2 && V2_ONLY.contains(k)) {
+                return Err(error(format!(
+                    "{} is not part of a version 1 snapshot",
+                    key
+                )));
+            }
+            match (key, words.next(), words.next(), words.next()) {
+                (None, ..) => (),
+                (Some("ip"), Some(ip), None, None) => {
+                    machine.set_ip(ip.parse().map_err(|_| error(format!("bad ip {}", ip)))?)
+                }
+                (Some("relative_base"), Some(base), None, None) => machine.set_relative_base(
+                    base.parse()
+                        .map_err(|_| error(format!("bad relative base {}", base)))?,
+                ),
+                (Some("cycles"), Some(cycles), None, None) => machine.set_cycles(
+                    cycles
+                        .parse()
+                        .map_err(|_| error(format!("bad cycle count {}", cycles)))?,
+                ),
+                (Some("halted"), Some(halted), None, None) => machine.set_halted(
+                    halted
+                        .parse()
+                        .map_err(|_| error(format!("bad halted flag {}", halted)))?,
+                ),
+                (Some("arithmetic"), Some(mode), None, None) => {
+                    machine.set_arithmetic(match mode {
+                        "wrapping" => Arithmetic::Wrapping,
+                        "checked" => Arithmetic::Checked,
+                        _ => return Err(error(format!("bad arithmetic {}", mode))),
+                    })
+                }
+                (Some("instruction_limit"), Some(limit), None, None) => {
+                    let limit = limit
+                        .parse()
+                        .map_err(|_| error(format!("bad instruction limit {}", limit)))?;
+                    machine.set_limits(Limits {
+                        instructions: Some(limit),
+                        ..machine.limits()
+                    })
+                }
+                (Some("memory_limit"), Some(limit), None, None) => {
+                    let limit = limit
+                        .parse()
+                        .map_err(|_| error(format!("bad memory limit {}", limit)))?;
+                    machine.set_limits(Limits {
+                        memory: Some(limit),
+                        ..machine.limits()
+                    })
+                }
+                (Some("input"), values, None, None) => {
+                    machine.extend_input(parse_words(values.unwrap_or("")).map_err(error)?)
+                }
+                (Some("memory"), Some(address), values, None) => {
+                    let address: usize = address
+                        .parse()
+                        .map_err(|_| error(format!("bad address {}", address)))?;
+                    let values = parse_words(values.unwrap_or("")).map_err(error)?;
+                    if address.checked_add(values.len()).is_none() {
+                        return Err(error(format!(
+                            "memory at {} runs past the last address",
+                            address
+                        )));
+                    }
+                    for (offset, value) in values.into_iter().enumerate() {
+                        machine.write(address + offset, value);
+                    }
+                }
+                _ => return Err(error(format!("unexpected {:?}", line))),
+            }
+        }
+
+        Ok(machine)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Machine<W, M>, SnapshotError> {
+        Machine::from_snapshot(&std::fs::read_to_string(path)?)
+    }
+}
+
+fn parse_words<W: Word>(text: &str) -> Result<Vec<W>, String> {
+    if text.is_empty() {
+        return Ok(vec![]);
+    }
+    text.split(',')
+        .map(|x| x.parse().map_err(|_| format!("non-integer {:?}", x)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Machine, State};
+
+    #[test]
+    fn round_trip() {
+        // adds up its inputs until it sees a zero
+        let program = crate::asm::assemble(
+            "
+                    arb #100
+            loop:   in [value]
+                    jz [value], #done
+                    add [value], [sum], [sum]
+                    jz #0, #loop
+            done:   out [sum]
+                    hlt
+            value:  .data 0
+            sum:    .data 0
+            ",
+        )
+        .unwrap();
+        let mut machine = Machine::new(program);
+        machine.extend_input(vec![5, 6, 0]);
+        for _ in 0..4 {
+            assert_eq!(machine.step(), Ok(State::Running));
+        }
+
+        let snapshot = machine.to_snapshot();
+        assert_eq!(
+            snapshot,
+            "intcode-snapshot 2
+ip 11
+relative_base 100
+cycles 4
+halted false
+arithmetic wrapping
+input 6,0
+memory 0 109,100,3,17,1006,17,14,1,17,18,18,1106,0,2,4,18,99,5,5
+"
+        );
+
+        let mut restored = <Machine>::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.run_until_blocked(), machine.run_until_blocked());
+        assert_eq!(restored.take_output(), machine.take_output());
+        assert_eq!(restored.into_memory(), machine.into_memory());
+    }
+
+    #[test]
+    fn settings() {
+        use crate::{Arithmetic, ErrorKind, Limit, Limits};
+
+        let mut machine = Machine::new(vec![1101, 1, 2, 7, 99]);
+        machine.set_arithmetic(Arithmetic::Checked);
+        machine.set_limits(Limits {
+            instructions: Some(10),
+            memory: Some(100),
+        });
+        assert_eq!(machine.run_until_blocked(), Ok(State::Halted));
+
+        let snapshot = machine.to_snapshot();
+        assert!(snapshot.contains("\ninstruction_limit 10\nmemory_limit 100\n"));
+        let restored = <Machine>::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.cycles(), 2);
+        assert!(restored.is_halted());
+        assert_eq!(restored.arithmetic(), Arithmetic::Checked);
+        assert_eq!(restored.limits(), machine.limits());
+
+        // the limits still apply once restored
+        let mut restored = <Machine>::from_snapshot(
+            "intcode-snapshot 2\nmemory_limit 100\nmemory 0 1101,1,2,100,99\n",
+        )
+        .unwrap();
+        assert_eq!(
+            restored.run_until_blocked().unwrap_err().kind,
+            ErrorKind::LimitExceeded(Limit::Memory(100))
+        );
+
+        // version 1 leaves them at their defaults
+        let old = <Machine>::from_snapshot("intcode-snapshot 1\nip 2\nmemory 0 99,0,99\n").unwrap();
+        assert_eq!(old.cycles(), 0);
+        assert!(!old.is_halted());
+        assert_eq!(old.limits(), Limits::default());
+    }
+
+    #[test]
+    fn other_words() {
+        use crate::memory::PagedMemory;
+        use num_bigint::BigInt;
+
+        // 2^70, too big for an isize
+        let big: BigInt = "1180591620717411303424".parse().unwrap();
+        let mut machine =
+            Machine::<BigInt>::with_program(vec![BigInt::from(104), big.clone(), BigInt::from(99)]);
+        machine.push_input(big.clone());
+
+        let mut restored =
+            Machine::<BigInt, PagedMemory<BigInt>>::from_snapshot(&machine.to_snapshot()).unwrap();
+        assert_eq!(restored.pending_input().front(), Some(&big));
+        assert_eq!(restored.run_until_io(), Ok(State::Output(big)));
+    }
+
+    #[test]
+    fn fork() {
+        let mut machine = Machine::new(vec![3, 9, 1001, 9, 10, 9, 4, 9, 99, 0]);
+        assert_eq!(machine.run_until_io(), Ok(State::NeedsInput));
+
+        let mut fork = machine.clone();
+        machine.push_input(1);
+        fork.push_input(2);
+        assert_eq!(machine.run_until_io(), Ok(State::Output(11)));
+        assert_eq!(fork.run_until_io(), Ok(State::Output(12)));
+    }
+
+    #[test]
+    fn sparse_memory_lines() {
+        let mut machine =
+            <Machine>::from_snapshot("intcode-snapshot 1\nmemory 0 4,10,99\nmemory 10 42\n")
+                .unwrap();
+        assert_eq!(machine.run_until_io(), Ok(State::Output(42)));
+        assert_eq!(machine.pending_input().len(), 0);
+    }
+
+    #[test]
+    fn paged_memory() {
+        use crate::memory::{Memory, PagedMemory, PAGE_SIZE};
+
+        let mut memory = PagedMemory::from(vec![4isize, 1_000_000_000_000, 99]);
+        memory.write(1_000_000_000_000, 42);
+        let snapshot = Machine::with_memory(memory).to_snapshot();
+        assert!(snapshot.contains(&format!(
+            "memory {} ",
+            1_000_000_000_000 / PAGE_SIZE * PAGE_SIZE
+        )));
+
+        let mut machine = <Machine>::from_snapshot(&snapshot).unwrap();
+        assert_eq!(machine.run_until_io(), Ok(State::Output(42)));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(<Machine>::from_snapshot("").is_err());
+        assert!(<Machine>::from_snapshot("intcode-snapshot 3\n").is_err());
+        assert!(<Machine>::from_snapshot("intcode-snapshot 2\narithmetic saturating\n").is_err());
+        // a word past the last address
+        let far = format!("intcode-snapshot 2\nmemory {} 1,2\n", usize::MAX);
+        match <Machine>::from_snapshot(&far) {
+            Err(super::SnapshotError::Parse { line, .. }) => assert_eq!(line, 2),
+            x => panic!("unexpected {:?}", x),
+        }
+        // fields from version 2 in a version 1 snapshot
+        assert!(<Machine>::from_snapshot("intcode-snapshot 1\ncycles 5\n").is_err());
+        match <Machine>::from_snapshot("intcode-snapshot 1\nip 0\nip x\n") {
+            Err(super::SnapshotError::Parse { line, .. }) => assert_eq!(line, 3),
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+}
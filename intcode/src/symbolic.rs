@@ -0,0 +1,692 @@
+// Runs a program with some of its memory or input left as named symbols, instead of numbers.
+//
+//     let mut machine = SymbolicMachine::new(&program);
+//     machine.set_symbol(1, "noun");
+//     machine.set_symbol(2, "verb");
+//     let exploration = symbolic::explore(machine, 10_000, 100);
+//     for path in &exploration.paths {
+//         let bounds = [("noun", 0..=99), ("verb", 0..=99)];
+//         println!("{:?}", path.solve(0, 19690720, &bounds));
+//     }
+//     assert!(exploration.is_complete());
+//
+// Arithmetic on symbols builds expressions.  A conditional jump on a symbolic value forks the
+// path in two, each remembering which way the condition went.  Reads through a symbolic address
+// give an opaque `Load`; writes and jumps to a symbolic address end the path with a fault.
+//
+// `solve` finds values for the symbols that make an expression equal a target, as long as the
+// expression is linear in them, like day 2's is.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use crate::instruction::Mode;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Const(isize),
+    Symbol(String),
+    // memory read through an address that was itself symbolic
+    Load(Rc<Expr>),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+    LessThan(Rc<Expr>, Rc<Expr>),
+    Equals(Rc<Expr>, Rc<Expr>),
+}
+
+impl Expr {
+    pub fn symbol(name: &str) -> Expr {
+        Expr::Symbol(name.to_string())
+    }
+
+    pub fn as_const(&self) -> Option<isize> {
+        match self {
+            Expr::Const(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    // The constructors below fold constants, so a program run without symbols only ever sees
+    // Const.
+    pub fn sum(a: Expr, b: Expr) -> Expr {
+        match (a.as_const(), b.as_const()) {
+            (Some(a), Some(b)) => Expr::Const(a.wrapping_add(b)),
+            (Some(0), _) => b,
+            (_, Some(0)) => a,
+            _ => Expr::Add(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn product(a: Expr, b: Expr) -> Expr {
+        match (a.as_const(), b.as_const()) {
+            (Some(a), Some(b)) => Expr::Const(a.wrapping_mul(b)),
+            (Some(0), _) | (_, Some(0)) => Expr::Const(0),
+            (Some(1), _) => b,
+            (_, Some(1)) => a,
+            _ => Expr::Mul(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a.as_const(), b.as_const()) {
+            (Some(a), Some(b)) => Expr::Const((a < b) as isize),
+            _ if a == b => Expr::Const(0),
+            _ => Expr::LessThan(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn equals(a: Expr, b: Expr) -> Expr {
+        match (a.as_const(), b.as_const()) {
+            (Some(a), Some(b)) => Expr::Const((a == b) as isize),
+            _ if a == b => Expr::Const(1),
+            _ => Expr::Equals(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    // None if the expression involves a symbol missing from `values`, or a Load.
+    pub fn evaluate(&self, values: &HashMap<String, isize>) -> Option<isize> {
+        Some(match self {
+            Expr::Const(x) => *x,
+            Expr::Symbol(name) => *values.get(name)?,
+            Expr::Load(_) => return None,
+            Expr::Add(a, b) => a.evaluate(values)?.wrapping_add(b.evaluate(values)?),
+            Expr::Mul(a, b) => a.evaluate(values)?.wrapping_mul(b.evaluate(values)?),
+            Expr::LessThan(a, b) => (a.evaluate(values)? < b.evaluate(values)?) as isize,
+            Expr::Equals(a, b) => (a.evaluate(values)? == b.evaluate(values)?) as isize,
+        })
+    }
+
+    pub fn symbols(&self) -> BTreeSet<String> {
+        let mut symbols = BTreeSet::new();
+        self.collect_symbols(&mut symbols);
+        symbols
+    }
+
+    fn collect_symbols(&self, symbols: &mut BTreeSet<String>) {
+        match self {
+            Expr::Const(_) => (),
+            Expr::Symbol(name) => {
+                symbols.insert(name.clone());
+            }
+            Expr::Load(a) => a.collect_symbols(symbols),
+            Expr::Add(a, b) | Expr::Mul(a, b) | Expr::LessThan(a, b) | Expr::Equals(a, b) => {
+                a.collect_symbols(symbols);
+                b.collect_symbols(symbols);
+            }
+        }
+    }
+
+    // The expression as `constant + sum(coefficient * symbol)`, if it can be written that way.
+    pub fn linear(&self) -> Option<Linear> {
+        match self {
+            Expr::Const(x) => Some(Linear {
+                terms: BTreeMap::new(),
+                constant: *x,
+            }),
+            Expr::Symbol(name) => Some(Linear {
+                terms: vec![(name.clone(), 1)].into_iter().collect(),
+                constant: 0,
+            }),
+            Expr::Add(a, b) => {
+                let mut sum = a.linear()?;
+                let b = b.linear()?;
+                sum.constant = sum.constant.wrapping_add(b.constant);
+                for (name, coefficient) in b.terms {
+                    let term = sum.terms.entry(name).or_insert(0);
+                    *term = term.wrapping_add(coefficient);
+                }
+                sum.terms.retain(|_, &mut c| c != 0);
+                Some(sum)
+            }
+            Expr::Mul(a, b) => {
+                let (a, b) = (a.linear()?, b.linear()?);
+                let (scale, mut product) = match (a.terms.is_empty(), b.terms.is_empty()) {
+                    (true, _) => (a.constant, b),
+                    (_, true) => (b.constant, a),
+                    _ => return None,
+                };
+                product.constant = product.constant.wrapping_mul(scale);
+                for coefficient in product.terms.values_mut() {
+                    *coefficient = coefficient.wrapping_mul(scale);
+                }
+                product.terms.retain(|_, &mut c| c != 0);
+                Some(product)
+            }
+            Expr::Load(_) | Expr::LessThan(_, _) | Expr::Equals(_, _) => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(x) => write!(f, "{}", x),
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::Load(a) => write!(f, "[{}]", a),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Linear {
+    pub terms: BTreeMap<String, isize>,
+    pub constant: isize,
+}
+
+// A branch taken along a path: `condition` was non-zero if `holds`, and zero otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Constraint {
+    pub condition: Expr,
+    pub holds: bool,
+}
+
+impl Constraint {
+    fn check(&self, values: &HashMap<String, isize>) -> bool {
+        self.condition
+            .evaluate(values)
+            .is_some_and(|x| (x != 0) == self.holds)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SymbolicMachine {
+    // sparse, so that a write far past the program costs no more than any other; missing words
+    // are zero
+    memory: BTreeMap<usize, Expr>,
+    ip: usize,
+    relative_base: Expr,
+    input: VecDeque<Expr>,
+    output: Vec<Expr>,
+    constraints: Vec<Constraint>,
+    steps: u64,
+}
+
+// How a path ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum End {
+    Halted,
+    NeedsInput,
+    Fault { ip: usize, message: String },
+    StepLimit,
+}
+
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub end: End,
+    pub machine: SymbolicMachine,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &[isize]) -> SymbolicMachine {
+        SymbolicMachine {
+            memory: program
+                .iter()
+                .enumerate()
+                .map(|(address, &x)| (address, Expr::Const(x)))
+                .collect(),
+            ip: 0,
+            relative_base: Expr::Const(0),
+            input: VecDeque::new(),
+            output: Vec::new(),
+            constraints: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn set_symbol(&mut self, address: usize, name: &str) {
+        self.write(address, Expr::symbol(name));
+    }
+
+    pub fn push_input(&mut self, value: Expr) {
+        self.input.push_back(value);
+    }
+
+    pub fn read(&self, address: usize) -> Expr {
+        self.memory.get(&address).cloned().unwrap_or(Expr::Const(0))
+    }
+
+    pub fn write(&mut self, address: usize, value: Expr) {
+        self.memory.insert(address, value);
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn output(&self) -> &[Expr] {
+        &self.output
+    }
+
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    // Runs until the path ends, or forks.  A fork is returned as the machine for the other
+    // branch, with `self` carrying on down the first.
+    fn run(&mut self, max_steps: u64) -> Result<End, Fork> {
+        loop {
+            if self.steps >= max_steps {
+                return Ok(End::StepLimit);
+            }
+            match self.step() {
+                Ok(None) => self.steps += 1,
+                Ok(Some(end)) => return Ok(end),
+                Err(fork) => {
+                    self.steps += 1;
+                    return Err(fork);
+                }
+            }
+        }
+    }
+
+    fn fault(&self, message: &str) -> Result<Option<End>, Fork> {
+        Ok(Some(End::Fault {
+            ip: self.ip,
+            message: message.to_string(),
+        }))
+    }
+
+    fn step(&mut self) -> Result<Option<End>, Fork> {
+        let instruction = match self.read(self.ip).as_const() {
+            Some(x) => x,
+            None => return self.fault("symbolic instruction"),
+        };
+        let modes = |idx: u32| Mode::from_code(instruction / 10isize.pow(idx + 1) % 10);
+
+        match instruction % 100 {
+            opcode @ (1 | 2 | 7 | 8) => {
+                let (a, b) = match (self.operand(1, modes(1)), self.operand(2, modes(2))) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => return self.fault("invalid mode"),
+                };
+                let value = match opcode {
+                    1 => Expr::sum(a, b),
+                    2 => Expr::product(a, b),
+                    7 => Expr::less_than(a, b),
+                    _ => Expr::equals(a, b),
+                };
+                match self.destination(3, modes(3)) {
+                    Ok(address) => self.write(address, value),
+                    Err(message) => return self.fault(message),
+                }
+                self.ip += 4;
+            }
+            3 => {
+                let address = match self.destination(1, modes(1)) {
+                    Ok(address) => address,
+                    Err(message) => return self.fault(message),
+                };
+                match self.input.pop_front() {
+                    Some(value) => self.write(address, value),
+                    None => return Ok(Some(End::NeedsInput)),
+                }
+                self.ip += 2;
+            }
+            4 => {
+                match self.operand(1, modes(1)) {
+                    Some(value) => self.output.push(value),
+                    None => return self.fault("invalid mode"),
+                }
+                self.ip += 2;
+            }
+            opcode @ (5 | 6) => {
+                let (condition, target) =
+                    match (self.operand(1, modes(1)), self.operand(2, modes(2))) {
+                        (Some(a), Some(b)) => (a, b),
+                        _ => return self.fault("invalid mode"),
+                    };
+                let target = match target.as_const() {
+                    Some(x) if x >= 0 => x as usize,
+                    Some(_) => return self.fault("invalid jump address"),
+                    None => return self.fault("symbolic jump address"),
+                };
+                let jump_if_nonzero = opcode == 5;
+                match condition.as_const() {
+                    Some(x) if (x != 0) == jump_if_nonzero => self.ip = target,
+                    Some(_) => self.ip += 3,
+                    None => {
+                        let mut other = self.clone();
+                        self.constraints.push(Constraint {
+                            condition: condition.clone(),
+                            holds: jump_if_nonzero,
+                        });
+                        self.ip = target;
+                        other.constraints.push(Constraint {
+                            condition,
+                            holds: !jump_if_nonzero,
+                        });
+                        other.ip += 3;
+                        other.steps += 1;
+                        return Err(Fork(Box::new(other)));
+                    }
+                }
+            }
+            9 => {
+                match self.operand(1, modes(1)) {
+                    Some(x) => self.relative_base = Expr::sum(self.relative_base.clone(), x),
+                    None => return self.fault("invalid mode"),
+                }
+                self.ip += 2;
+            }
+            99 => return Ok(Some(End::Halted)),
+            _ => return self.fault("unknown opcode"),
+        }
+        Ok(None)
+    }
+
+    fn address(&self, idx: usize, mode: Mode) -> Expr {
+        let parameter = self.read(self.ip + idx);
+        match mode {
+            Mode::Relative => Expr::sum(parameter, self.relative_base.clone()),
+            _ => parameter,
+        }
+    }
+
+    fn operand(&self, idx: usize, mode: Option<Mode>) -> Option<Expr> {
+        Some(match mode? {
+            Mode::Immediate => self.read(self.ip + idx),
+            mode => match self.address(idx, mode) {
+                Expr::Const(x) if x >= 0 => self.read(x as usize),
+                address => Expr::Load(Rc::new(address)),
+            },
+        })
+    }
+
+    fn destination(&self, idx: usize, mode: Option<Mode>) -> Result<usize, &'static str> {
+        match mode {
+            Some(Mode::Immediate) | None => Err("invalid mode"),
+            Some(mode) => match self.address(idx, mode) {
+                Expr::Const(x) if x >= 0 => Ok(x as usize),
+                Expr::Const(_) => Err("invalid address"),
+                _ => Err("symbolic address"),
+            },
+        }
+    }
+}
+
+struct Fork(Box<SymbolicMachine>);
+
+#[derive(Clone, Debug)]
+pub struct Exploration {
+    // in the order they ended
+    pub paths: Vec<Path>,
+    // forks that were not followed, because there were already max_paths paths
+    pub dropped: usize,
+}
+
+impl Exploration {
+    // Whether every path was followed to its end, so that the paths cover every way through the
+    // program.
+    pub fn is_complete(&self) -> bool {
+        self.dropped == 0 && self.paths.iter().all(|p| p.end != End::StepLimit)
+    }
+}
+
+// Follows every path through the program, giving each at most `max_steps` instructions, and
+// stops forking once there are `max_paths` of them.
+pub fn explore(machine: SymbolicMachine, max_steps: u64, max_paths: usize) -> Exploration {
+    let mut pending = vec![machine];
+    let mut started = 1;
+    let mut exploration = Exploration {
+        paths: Vec::new(),
+        dropped: 0,
+    };
+    while let Some(mut machine) = pending.pop() {
+        let end = loop {
+            match machine.run(max_steps) {
+                Ok(end) => break end,
+                Err(Fork(other)) if started < max_paths => {
+                    started += 1;
+                    pending.push(*other);
+                }
+                Err(_) => exploration.dropped += 1,
+            }
+        };
+        exploration.paths.push(Path { end, machine });
+    }
+    exploration
+}
+
+// Why solve found no values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Unsolved {
+    NotLinear,
+    // a symbol in the expression was given no bounds
+    Unbounded(String),
+    // the bounds are so wide that the search would overflow
+    Overflow,
+    NoSolution,
+}
+
+impl fmt::Display for Unsolved {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unsolved::NotLinear => write!(f, "expression is not linear"),
+            Unsolved::Unbounded(name) => write!(f, "no bounds for {}", name),
+            Unsolved::Overflow => write!(f, "bounds too wide"),
+            Unsolved::NoSolution => write!(f, "no solution within the bounds"),
+        }
+    }
+}
+
+impl Path {
+    // Values within `bounds` for every symbol, such that memory at `address` is `target` and
+    // the path's constraints hold.
+    pub fn solve(
+        &self,
+        address: usize,
+        target: isize,
+        bounds: &[(&str, RangeInclusive<isize>)],
+    ) -> Result<HashMap<String, isize>, Unsolved> {
+        solve(
+            &self.machine.read(address),
+            target,
+            bounds,
+            &self.machine.constraints,
+        )
+    }
+}
+
+// Finds values within `bounds` for the symbols of a linear expression, such that it equals
+// `target` and every constraint holds.  Symbols without bounds may only have a coefficient of 0.
+pub fn solve(
+    expr: &Expr,
+    target: isize,
+    bounds: &[(&str, RangeInclusive<isize>)],
+    constraints: &[Constraint],
+) -> Result<HashMap<String, isize>, Unsolved> {
+    let linear = expr.linear().ok_or(Unsolved::NotLinear)?;
+    let mut terms = Vec::new();
+    for (name, &coefficient) in &linear.terms {
+        let (_, range) = bounds
+            .iter()
+            .find(|(n, _)| n == name)
+            .ok_or_else(|| Unsolved::Unbounded(name.clone()))?;
+        terms.push((name.as_str(), coefficient, range.clone()));
+    }
+    // the biggest coefficients first prunes the most
+    terms.sort_by_key(|&(_, coefficient, _)| std::cmp::Reverse(coefficient.unsigned_abs()));
+
+    let mut values: HashMap<String, isize> = bounds
+        .iter()
+        .filter(|(name, _)| !linear.terms.contains_key(*name))
+        .map(|(name, range)| (name.to_string(), *range.start()))
+        .collect();
+    let remaining = target
+        .checked_sub(linear.constant)
+        .ok_or(Unsolved::Overflow)?;
+    // checked up front, so that the search need not try every value of an overly wide range
+    extent(&terms)?;
+    if search(&terms, remaining, &mut values, constraints)? {
+        Ok(values)
+    } else {
+        Err(Unsolved::NoSolution)
+    }
+}
+
+// The smallest and largest that `sum(coefficient * symbol)` can be over `terms`.
+fn extent(terms: &[(&str, isize, RangeInclusive<isize>)]) -> Result<(isize, isize), Unsolved> {
+    let mut extent = (0isize, 0isize);
+    for (_, coefficient, range) in terms {
+        let a = coefficient.checked_mul(*range.start());
+        let b = coefficient.checked_mul(*range.end());
+        let (a, b) = a.zip(b).ok_or(Unsolved::Overflow)?;
+        extent = extent
+            .0
+            .checked_add(a.min(b))
+            .zip(extent.1.checked_add(a.max(b)))
+            .ok_or(Unsolved::Overflow)?;
+    }
+    Ok(extent)
+}
+
+fn search(
+    terms: &[(&str, isize, RangeInclusive<isize>)],
+    remaining: isize,
+    values: &mut HashMap<String, isize>,
+    constraints: &[Constraint],
+) -> Result<bool, Unsolved> {
+    let ((name, coefficient, range), rest) = match terms.split_first() {
+        Some(x) => x,
+        None => {
+            return Ok(remaining == 0 && constraints.iter().all(|c| c.check(values)));
+        }
+    };
+    if rest.is_empty() {
+        // the last symbol has at most one value that makes up what remains
+        let value = match (
+            remaining.checked_rem(*coefficient),
+            remaining.checked_div(*coefficient),
+        ) {
+            (Some(0), Some(value)) if range.contains(&value) => value,
+            _ => return Ok(false),
+        };
+        values.insert(name.to_string(), value);
+        if constraints.iter().all(|c| c.check(values)) {
+            return Ok(true);
+        }
+        values.remove(*name);
+        return Ok(false);
+    }
+    let (min, max) = extent(rest)?;
+    for value in range.clone() {
+        // the rest's extent fits in an isize, so whatever does not cannot be made up by it
+        let left = match coefficient
+            .checked_mul(value)
+            .and_then(|x| remaining.checked_sub(x))
+        {
+            Some(left) if min <= left && left <= max => left,
+            _ => continue,
+        };
+        values.insert(name.to_string(), value);
+        if search(rest, left, values, constraints)? {
+            return Ok(true);
+        }
+    }
+    values.remove(*name);
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{explore, End, Expr, SymbolicMachine, Unsolved};
+
+    fn day_02() -> Vec<isize> {
+        crate::parse_opcodes(&std::fs::read_to_string("../inputs/day_02.txt").unwrap())
+    }
+
+    #[test]
+    fn linear() {
+        let mut machine = SymbolicMachine::new(&day_02());
+        machine.set_symbol(1, "noun");
+        machine.set_symbol(2, "verb");
+        let exploration = explore(machine, 10_000, 10);
+        assert!(exploration.is_complete());
+        let paths = exploration.paths;
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].end, End::Halted);
+
+        let linear = paths[0].machine.read(0).linear().unwrap();
+        assert_eq!(linear.terms.len(), 2);
+        assert_eq!(linear.terms["verb"], 1);
+
+        let bounds = [("noun", 0..=99), ("verb", 0..=99)];
+        let solution = paths[0].solve(0, 3101878, &bounds).unwrap();
+        assert_eq!((solution["noun"], solution["verb"]), (12, 2));
+        assert_eq!(paths[0].solve(0, -1, &bounds), Err(Unsolved::NoSolution));
+        // the last symbol is worked out rather than searched for, so its range can be huge
+        let wide = [("noun", 0..=99), ("verb", 0..=1_000_000_000_000_000)];
+        assert_eq!(paths[0].solve(0, -1, &wide), Err(Unsolved::NoSolution));
+        let solution = paths[0].solve(0, 1_000_000_000_000, &wide).unwrap();
+        assert_eq!(
+            paths[0].machine.read(0).evaluate(&solution),
+            Some(1_000_000_000_000)
+        );
+        // bounds so wide that the coefficients times them do not fit
+        let bounds = [("noun", isize::MIN..=isize::MAX), ("verb", 0..=99)];
+        assert_eq!(paths[0].solve(0, 0, &bounds), Err(Unsolved::Overflow));
+    }
+
+    #[test]
+    fn branches() {
+        // outputs 1 if the input is less than 10, and 2 otherwise
+        let program = crate::asm::assemble(
+            "
+                    in [x]
+                    lt [x], #10, [t]
+                    jz [t], #big
+                    out #1
+                    hlt
+            big:    out #2
+                    hlt
+            x:      .data 0
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut machine = SymbolicMachine::new(&program);
+        machine.push_input(Expr::symbol("x"));
+        let exploration = explore(machine.clone(), 100, 10);
+        assert!(exploration.is_complete());
+        let paths = exploration.paths;
+        assert_eq!(paths.len(), 2);
+
+        let bounds = [("x", -100..=100)];
+        for path in &paths {
+            assert_eq!(path.end, End::Halted);
+            assert_eq!(path.machine.constraints().len(), 1);
+            let expected = path.machine.output()[0].as_const().unwrap();
+            // the path that outputs 1 only works for small x, and vice versa
+            let x = super::solve(&Expr::symbol("x"), 50, &bounds, path.machine.constraints());
+            assert_eq!(x.is_ok(), expected == 2);
+            let x = super::solve(&Expr::symbol("x"), 5, &bounds, path.machine.constraints());
+            assert_eq!(x.is_ok(), expected == 1);
+        }
+        assert_eq!(
+            paths[0].machine.constraints()[0].condition.to_string(),
+            "(x < 10)"
+        );
+
+        // with room for only one path, the other side of the branch is dropped
+        let exploration = explore(machine, 100, 1);
+        assert_eq!((exploration.paths.len(), exploration.dropped), (1, 1));
+        assert!(!exploration.is_complete());
+    }
+
+    #[test]
+    fn far_write() {
+        // writes the input a trillion words past the end, then outputs it from there
+        let mut machine = SymbolicMachine::new(&[3, 1_000_000_000_000, 4, 1_000_000_000_000, 99]);
+        machine.push_input(Expr::symbol("x"));
+        let paths = explore(machine, 100, 10).paths;
+        assert_eq!(paths[0].end, End::Halted);
+        assert_eq!(paths[0].machine.output(), &[Expr::symbol("x")]);
+        assert_eq!(paths[0].machine.read(1_000_000_000_001), Expr::Const(0));
+    }
+}
@@ -0,0 +1,439 @@
+// Tracks which input values each memory cell, and each output, depends on.
+//
+//     let mut taint = Taint::new(&program);
+//     machine.run_until_blocked_with(&mut taint)?;
+//     print!("{}", taint.report());
+//
+// Inputs are numbered from 0 in the order the program reads them.  A value depends on an input
+// if the input reaches it through arithmetic and comparisons, through the address it was read
+// from or written to (including the relative base), or through a conditional jump that was
+// decided by one.
+//
+// Jumps are the approximate part.  A jump whose condition or target depends on some inputs makes
+// everything written or output afterwards depend on them too, until execution reaches the point
+// where the two paths meet again.  That is the jump's post-dominator in the control flow graph of
+// the program as loaded (see cfg); for a jump the graph cannot place, such as one in code that
+// was rewritten or one followed by a return through the stack, the dependency lasts until the end
+// of the run.  Dependencies that come from a path not being taken at all are not seen.
+//
+// A Taint should watch a machine from the start: it numbers inputs as it sees them read, and
+// works out relative addresses from the relative base it has seen set.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::instruction::Mode;
+use crate::{cfg, Observer};
+
+pub type Inputs = BTreeSet<usize>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Output {
+    pub value: isize,
+    pub inputs: Inputs,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Taint {
+    // only cells that depend on some input are present
+    memory: HashMap<usize, Inputs>,
+    relative_base: isize,
+    // what the relative base depends on through data, and through control
+    relative_base_inputs: Inputs,
+    relative_base_control: Inputs,
+    // earlier relative bases: (value, relative_base_control, control inputs when it changed)
+    relative_bases: Vec<(isize, Inputs, Inputs)>,
+    // conditional jump -> where the paths from it meet again, if they do
+    joins: HashMap<usize, usize>,
+    // (where the paths meet again, what the jump depended on)
+    control: Vec<(Option<usize>, Inputs)>,
+    inputs_read: usize,
+    outputs: Vec<Output>,
+    current: Option<Step>,
+}
+
+// What the observer has seen of the instruction being executed.
+#[derive(Clone, Debug)]
+struct Step {
+    ip: usize,
+    opcode: isize,
+    // (mode, parameter, value read or address written)
+    operands: Vec<(Mode, isize, isize)>,
+    writes: Vec<usize>,
+}
+
+// How many earlier relative bases are remembered.
+const RELATIVE_BASES: usize = 1000;
+
+impl Taint {
+    // `program` is the program the machine was loaded with, to find where branches meet again.
+    pub fn new(program: &[isize]) -> Taint {
+        let cfg = cfg::build(program);
+        let mut joins = HashMap::new();
+        for (start, join) in cfg.post_dominators() {
+            if let Some(&(address, _)) = cfg.blocks[&start].instructions.last() {
+                joins.insert(address, join);
+            }
+        }
+        Taint {
+            joins,
+            ..Taint::default()
+        }
+    }
+
+    // What the value at `address` currently depends on.
+    pub fn inputs_of(&self, address: usize) -> Inputs {
+        self.memory.get(&address).cloned().unwrap_or_default()
+    }
+
+    pub fn inputs_read(&self) -> usize {
+        self.inputs_read
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    // One line per output: its index, its value and the inputs it depends on.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for (i, output) in self.outputs.iter().enumerate() {
+            let inputs: Vec<String> = output.inputs.iter().map(|x| x.to_string()).collect();
+            report += &format!(
+                "output {} ({}): {}\n",
+                i,
+                output.value,
+                if inputs.is_empty() {
+                    "no inputs".to_string()
+                } else {
+                    format!("input(s) {}", inputs.join(", "))
+                }
+            );
+        }
+        report
+    }
+
+    fn control_inputs(&self) -> Inputs {
+        self.control
+            .iter()
+            .flat_map(|(_, inputs)| inputs.iter().cloned())
+            .collect()
+    }
+
+    // What an operand depends on: the parameter word itself, and for a read the cell it was read
+    // from, or for a write the address it names.
+    fn operand_inputs(
+        &self,
+        ip: usize,
+        idx: usize,
+        mode: Mode,
+        parameter: isize,
+        write: bool,
+    ) -> Inputs {
+        let mut inputs = self.inputs_of(ip + idx + 1);
+        let address = match mode {
+            Mode::Immediate => return inputs,
+            Mode::Position => parameter,
+            Mode::Relative => {
+                inputs.extend(self.relative_base_inputs.iter().cloned());
+                inputs.extend(self.relative_base_control.iter().cloned());
+                parameter.wrapping_add(self.relative_base)
+            }
+        };
+        if !write && address >= 0 {
+            inputs.extend(self.inputs_of(address as usize));
+        }
+        inputs
+    }
+
+    // A relative base that goes back to an earlier value, as it does when a subroutine returns,
+    // no longer depends on jumps taken since it left that value, as long as it left and came back
+    // under the same ones: whichever way they went, it ends up the same.  Data dependencies stay.
+    fn adjust_relative_base(&mut self, offset: isize, operands: Inputs, control: Inputs) {
+        let base = self.relative_base.wrapping_add(offset);
+        self.relative_base_inputs.extend(operands);
+        match self.relative_bases.iter().rposition(|(b, _, _)| *b == base) {
+            Some(idx) => {
+                let (_, mut inputs, then) = self.relative_bases[idx].clone();
+                self.relative_bases.truncate(idx);
+                inputs.extend(control.difference(&then).cloned());
+                self.relative_base_control = inputs;
+            }
+            None => {
+                self.relative_bases.push((
+                    self.relative_base,
+                    self.relative_base_control.clone(),
+                    control.clone(),
+                ));
+                if self.relative_bases.len() > RELATIVE_BASES {
+                    self.relative_bases.remove(0);
+                }
+                self.relative_base_control.extend(control);
+            }
+        }
+        self.relative_base = base;
+    }
+
+    fn retire_instruction(&mut self, instruction: Step, next_ip: usize) {
+        // which operand, if any, is a destination
+        let destination = match instruction.opcode {
+            1 | 2 | 7 | 8 => Some(2),
+            3 => Some(0),
+            _ => None,
+        };
+        let operands: Vec<Inputs> = instruction
+            .operands
+            .iter()
+            .enumerate()
+            .map(|(idx, &(mode, parameter, _))| {
+                self.operand_inputs(
+                    instruction.ip,
+                    idx,
+                    mode,
+                    parameter,
+                    destination == Some(idx),
+                )
+            })
+            .collect();
+        let control = self.control_inputs();
+
+        let mut data: Inputs = operands.iter().flatten().cloned().collect();
+        data.extend(control.iter().cloned());
+        match instruction.opcode {
+            3 => {
+                data.insert(self.inputs_read);
+                self.inputs_read += 1;
+            }
+            4 => self.outputs.push(Output {
+                value: instruction.operands[0].2,
+                inputs: data.clone(),
+            }),
+            5 | 6 => {
+                let join = self.joins.get(&instruction.ip).cloned();
+                let mut inputs = operands[0].clone();
+                inputs.extend(operands[1].iter().cloned());
+                if !inputs.is_empty() && Some(next_ip) != join {
+                    // one entry per join, as they all end there together; a loop would
+                    // otherwise add another on every iteration
+                    match self.control.iter_mut().find(|(j, _)| *j == join) {
+                        Some((_, existing)) => existing.extend(inputs),
+                        None => self.control.push((join, inputs)),
+                    }
+                }
+            }
+            9 => self.adjust_relative_base(
+                instruction.operands[0].2,
+                operands.iter().flatten().cloned().collect(),
+                control.clone(),
+            ),
+            _ => (),
+        }
+
+        for address in instruction.writes {
+            if data.is_empty() {
+                self.memory.remove(&address);
+            } else {
+                self.memory.insert(address, data.clone());
+            }
+        }
+    }
+}
+
+impl Observer for Taint {
+    fn begin(&mut self, ip: usize, &instruction: &isize) {
+        self.control.retain(|&(join, _)| join != Some(ip));
+        self.current = Some(Step {
+            ip,
+            opcode: instruction % 100,
+            operands: Vec::new(),
+            writes: Vec::new(),
+        });
+    }
+
+    fn operand(&mut self, mode: Mode, &parameter: &isize, &value: &isize) {
+        if let Some(current) = &mut self.current {
+            current.operands.push((mode, parameter, value));
+        }
+    }
+
+    fn write(&mut self, address: usize, _old: &isize, _new: &isize) {
+        if let Some(current) = &mut self.current {
+            current.writes.push(address);
+        }
+    }
+
+    fn retire(&mut self, next_ip: usize) {
+        if let Some(instruction) = self.current.take() {
+            self.retire_instruction(instruction, next_ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Taint;
+    use crate::Machine;
+
+    #[test]
+    fn data_flow() {
+        // outputs a+b, c*2, a constant, and then one of two constants depending on whether
+        // d < 5
+        let program = crate::asm::assemble(
+            "
+                    in [a]
+                    in [b]
+                    in [c]
+                    in [d]
+                    add [a], [b], [t]
+                    out [t]
+                    mul [c], #2, [t]
+                    out [t]
+                    out #7
+                    lt [d], #5, [t]
+                    jz [t], #big
+                    out #0
+                    jz #0, #done
+            big:    out #1
+            done:   hlt
+            a:      .data 0
+            b:      .data 0
+            c:      .data 0
+            d:      .data 0
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut taint = Taint::new(&program);
+        let mut machine = Machine::new(program);
+        machine.extend_input(vec![1, 2, 3, 4]);
+        machine.run_until_blocked_with(&mut taint).unwrap();
+
+        assert_eq!(taint.inputs_read(), 4);
+        assert_eq!(
+            taint.report(),
+            "output 0 (3): input(s) 0, 1\n\
+             output 1 (6): input(s) 2\n\
+             output 2 (7): no inputs\n\
+             output 3 (0): input(s) 3\n"
+        );
+    }
+
+    #[test]
+    fn addresses() {
+        // reads an index and outputs table[index], via the relative base
+        let program = crate::asm::assemble(
+            "
+                    in [i]
+                    arb [i]
+                    arb #table
+                    out rb+0
+                    out #table
+                    hlt
+            i:      .data 0
+            table:  .data 10, 20, 30
+            ",
+        )
+        .unwrap();
+        let mut taint = Taint::new(&program);
+        let mut machine = Machine::new(program);
+        machine.extend_input(vec![2]);
+        machine.run_until_blocked_with(&mut taint).unwrap();
+
+        let outputs: Vec<_> = taint
+            .outputs()
+            .iter()
+            .map(|o| (o.value, o.inputs.iter().cloned().collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(outputs, vec![(30, vec![0]), (12, vec![])]);
+        assert!(taint.inputs_of(11).contains(&0));
+    }
+
+    #[test]
+    fn branches() {
+        // outputs 0 or 1 depending on whether d < 5, then a constant once the two sides meet
+        let program = crate::asm::assemble(
+            "
+                    in [d]
+                    lt [d], #5, [t]
+                    jz [t], #big
+                    out #0
+                    jz #0, #done
+            big:    out #1
+            done:   out #2
+                    hlt
+            d:      .data 0
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        for &d in &[4, 6] {
+            let mut taint = Taint::new(&program);
+            let mut machine = Machine::new(program.clone());
+            machine.push_input(d);
+            machine.run_until_blocked_with(&mut taint).unwrap();
+            let inputs: Vec<Vec<usize>> = taint
+                .outputs()
+                .iter()
+                .map(|o| o.inputs.iter().cloned().collect())
+                .collect();
+            assert_eq!(inputs, vec![vec![0], vec![]], "d = {}", d);
+        }
+    }
+
+    #[test]
+    fn loops() {
+        // counts its input down to zero
+        let program = crate::asm::assemble(
+            "
+                    in [n]
+            loop:   add [n], #-1, [n]
+                    jnz [n], #loop
+                    out #7
+                    hlt
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut taint = Taint::new(&program);
+        let mut machine = Machine::new(program);
+        machine.push_input(1000);
+        for _ in 0..1000 {
+            machine.step_with(&mut taint).unwrap();
+        }
+        // every iteration jumps back on the input, but that is one branch still to join
+        assert_eq!(taint.control.len(), 1);
+        machine.run_until_blocked_with(&mut taint).unwrap();
+        assert!(taint.control.is_empty());
+        assert!(taint.outputs()[0].inputs.is_empty());
+    }
+
+    #[test]
+    fn relative_base_returns() {
+        // a subroutine call, only made for some inputs, that moves the relative base and puts it
+        // back; afterwards the relative base is the same whichever way the jump went
+        let program = crate::asm::assemble(
+            "
+                    arb #data
+                    in [x]
+                    jz [x], #skip
+                    arb #1
+                    out rb+0
+                    arb #-1
+            skip:   out rb+0
+                    hlt
+            x:      .data 0
+            data:   .data 7, 8
+            ",
+        )
+        .unwrap();
+        let mut taint = Taint::new(&program);
+        let mut machine = Machine::new(program);
+        machine.push_input(1);
+        machine.run_until_blocked_with(&mut taint).unwrap();
+        let outputs: Vec<_> = taint
+            .outputs()
+            .iter()
+            .map(|o| (o.value, o.inputs.iter().cloned().collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(outputs, vec![(8, vec![0]), (7, vec![])]);
+    }
+}
@@ -0,0 +1,252 @@
+// Records what the interpreter did, one Record per retired instruction.
+//
+//     let mut tracer = Tracer::new(JsonLines::create("trace.jsonl")?);
+//     machine.run_until_blocked_with(&mut tracer)?;
+//     tracer.into_sink().finish()?;
+//
+// Any `FnMut(&Record)` closure works as a sink too.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::instruction::Mode;
+use crate::Observer;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceOperand {
+    pub mode: Mode,
+    pub parameter: isize,
+    // the value that was read, or for a destination, the address that was written
+    pub value: isize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old: isize,
+    pub new: isize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub ip: usize,
+    pub instruction: isize,
+    pub operands: Vec<TraceOperand>,
+    pub writes: Vec<MemoryWrite>,
+    // the new relative base, if the instruction changed it
+    pub relative_base: Option<isize>,
+}
+
+impl Record {
+    pub fn opcode(&self) -> isize {
+        self.instruction % 100
+    }
+
+    pub fn to_json(&self) -> String {
+        let operands: Vec<String> = self
+            .operands
+            .iter()
+            .map(|o| {
+                format!(
+                    "{{\"mode\":\"{}\",\"parameter\":{},\"value\":{}}}",
+                    mode_name(o.mode),
+                    o.parameter,
+                    o.value
+                )
+            })
+            .collect();
+        let writes: Vec<String> = self
+            .writes
+            .iter()
+            .map(|w| {
+                format!(
+                    "{{\"address\":{},\"old\":{},\"new\":{}}}",
+                    w.address, w.old, w.new
+                )
+            })
+            .collect();
+        let relative_base = match self.relative_base {
+            Some(x) => x.to_string(),
+            None => "null".to_string(),
+        };
+
+        format!(
+            "{{\"ip\":{},\"instruction\":{},\"opcode\":{},\"operands\":[{}],\"writes\":[{}],\"relative_base\":{}}}",
+            self.ip,
+            self.instruction,
+            self.opcode(),
+            operands.join(","),
+            writes.join(","),
+            relative_base
+        )
+    }
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Position => "position",
+        Mode::Immediate => "immediate",
+        Mode::Relative => "relative",
+    }
+}
+
+pub trait Sink {
+    fn record(&mut self, record: &Record);
+}
+
+impl<F: FnMut(&Record)> Sink for F {
+    fn record(&mut self, record: &Record) {
+        self(record)
+    }
+}
+
+pub struct Tracer<S: Sink> {
+    sink: S,
+    pending: Option<Record>,
+}
+
+impl<S: Sink> Tracer<S> {
+    pub fn new(sink: S) -> Tracer<S> {
+        Tracer {
+            sink,
+            pending: None,
+        }
+    }
+
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: Sink> Observer for Tracer<S> {
+    fn begin(&mut self, ip: usize, &instruction: &isize) {
+        // anything still pending never retired, so it does not belong in the trace
+        self.pending = Some(Record {
+            ip,
+            instruction,
+            operands: Vec::new(),
+            writes: Vec::new(),
+            relative_base: None,
+        });
+    }
+
+    fn operand(&mut self, mode: Mode, &parameter: &isize, &value: &isize) {
+        if let Some(record) = &mut self.pending {
+            record.operands.push(TraceOperand {
+                mode,
+                parameter,
+                value,
+            });
+        }
+    }
+
+    fn write(&mut self, address: usize, &old: &isize, &new: &isize) {
+        if let Some(record) = &mut self.pending {
+            record.writes.push(MemoryWrite { address, old, new });
+        }
+    }
+
+    fn relative_base(&mut self, _old: &isize, &new: &isize) {
+        if let Some(record) = &mut self.pending {
+            record.relative_base = Some(new);
+        }
+    }
+
+    fn retire(&mut self, _next_ip: usize) {
+        if let Some(record) = self.pending.take() {
+            self.sink.record(&record);
+        }
+    }
+}
+
+// Writes each record as a line of JSON.  The first I/O error stops any further writing, and is
+// reported by `finish`.
+pub struct JsonLines<W: Write> {
+    writer: W,
+    error: Option<std::io::Error>,
+}
+
+impl JsonLines<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<JsonLines<BufWriter<File>>> {
+        Ok(JsonLines::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(writer: W) -> JsonLines<W> {
+        JsonLines {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn finish(mut self) -> std::io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Sink for JsonLines<W> {
+    fn record(&mut self, record: &Record) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.writer, "{}", record.to_json()) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonLines, Record, Tracer};
+    use crate::{Machine, State};
+
+    #[test]
+    fn closure_sink() {
+        let mut records = Vec::new();
+        let mut machine = Machine::new(vec![109, 5, 21101, 2, 3, 0, 3, 0, 99]);
+        let mut tracer = Tracer::new(|r: &Record| records.push(r.clone()));
+
+        assert_eq!(
+            machine.run_until_io_with(&mut tracer),
+            Ok(State::NeedsInput)
+        );
+        machine.push_input(7);
+        assert_eq!(machine.run_until_io_with(&mut tracer), Ok(State::Halted));
+        std::mem::drop(tracer);
+
+        let ips: Vec<usize> = records.iter().map(|r| r.ip).collect();
+        assert_eq!(ips, vec![0, 2, 6, 8]);
+        assert_eq!(records[0].relative_base, Some(5));
+        assert_eq!(records[1].writes[0].address, 5);
+        assert_eq!(records[1].writes[0].old, 0);
+        assert_eq!(records[1].writes[0].new, 5);
+        assert_eq!(records[2].writes[0].new, 7);
+        assert_eq!(records[3].opcode(), 99);
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut machine = Machine::new(vec![1002, 4, 3, 4, 33]);
+        let mut tracer = Tracer::new(JsonLines::new(Vec::new()));
+        machine.run_until_blocked_with(&mut tracer).unwrap();
+        let output = String::from_utf8(tracer.into_sink().finish().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            concat!(
+                r#"{"ip":0,"instruction":1002,"opcode":2,"operands":["#,
+                r#"{"mode":"position","parameter":4,"value":33},"#,
+                r#"{"mode":"immediate","parameter":3,"value":3},"#,
+                r#"{"mode":"position","parameter":4,"value":4}],"#,
+                r#""writes":[{"address":4,"old":33,"new":99}],"relative_base":null}"#,
+                "\n",
+                r#"{"ip":4,"instruction":99,"opcode":99,"operands":[],"writes":[],"relative_base":null}"#,
+                "\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,611 @@
+// Compiles an intcode program to Rust or C source, for the intcode-transpile binary.
+//
+// Every address that decodes as an instruction gets its own label (a match arm in Rust), and
+// computed jumps dispatch on the target address.  Each label first checks that memory still holds
+// the instruction it was compiled from.  When it does not, the program has modified its own code:
+// the Rust version finishes the run in the interpreter, and the C version gives up with
+// INTCODE_SELF_MODIFYING.  C output is refused outright for programs that can be seen to write
+// into their own code without running them, like day 5's.
+//
+// Likewise for an address too far past the end of memory for the compiled code to grow into:
+// the interpreter accepts it, so Rust hands over to the interpreter and C gives up with
+// INTCODE_FAR_ADDRESS.  Only negative addresses are faults.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::instruction::{Instruction, Mode, Opcode, Operand};
+use crate::memory::AutoMemory;
+use crate::{cfg, Error, ErrorKind, Machine, State};
+
+// Writes this far past the end of memory are left to the interpreter, whose memory can cope.
+const GROWTH_LIMIT: usize = 1 << 20;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelfModifying {
+    // the instruction that does the writing, and where it writes
+    pub ip: usize,
+    pub address: usize,
+}
+
+impl fmt::Display for SelfModifying {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "instruction at {} writes to code at {}",
+            self.ip, self.address
+        )
+    }
+}
+
+impl std::error::Error for SelfModifying {}
+
+// Writes, through a constant address, into the code reachable from address 0.  That includes
+// words that execution runs into but that do not decode yet, as the program must be planning to
+// fill them in first.
+pub fn self_modifications(program: &[isize]) -> Vec<SelfModifying> {
+    let blocks = cfg::build(program).blocks;
+    let instructions: Vec<(usize, Instruction)> = blocks
+        .values()
+        .flat_map(|b| b.instructions.iter().cloned())
+        .collect();
+    let mut code: BTreeSet<usize> = instructions
+        .iter()
+        .flat_map(|&(address, i)| address..address + i.size())
+        .collect();
+    for block in blocks.values() {
+        let falls_through = match block.instructions.last() {
+            Some((_, i)) => !matches!(i.opcode, Opcode::Jnz | Opcode::Jz | Opcode::Hlt),
+            None => false,
+        };
+        if falls_through && block.successors.is_empty() {
+            code.insert(block.end());
+        }
+    }
+
+    let mut found = Vec::new();
+    for (ip, instruction) in instructions {
+        for (idx, operand) in instruction.operands().iter().enumerate() {
+            if instruction.opcode.writes(idx)
+                && operand.mode == Mode::Position
+                && operand.value >= 0
+                && code.contains(&(operand.value as usize))
+            {
+                found.push(SelfModifying {
+                    ip,
+                    address: operand.value as usize,
+                });
+            }
+        }
+    }
+    found
+}
+
+// Every address that decodes, whether or not anything ever jumps there.
+fn instructions(program: &[isize]) -> Vec<(usize, Instruction)> {
+    (0..program.len())
+        .filter_map(|a| Instruction::decode(program, a).map(|i| (a, i)))
+        .collect()
+}
+
+pub fn to_rust(program: &[isize]) -> String {
+    let mut out = String::new();
+    out += "// Generated by intcode-transpile; do not edit.\n";
+    out += "use intcode::transpile::{address, interpret, read, unchanged, write};\n";
+    out += "use intcode::{Error, ErrorKind};\n\n";
+    out += &format!(
+        "pub const PROGRAM: [isize; {}] = [{}];\n\n",
+        program.len(),
+        crate::format_opcodes(program).replace(',', ", ")
+    );
+    out += "\
+// Runs the program to completion, returning its final memory.
+#[allow(unused_mut, clippy::all)]
+pub fn run(
+    input: &mut dyn FnMut() -> Option<isize>,
+    output: &mut dyn FnMut(isize),
+) -> Result<Vec<isize>, Error> {
+    let mut m = PROGRAM.to_vec();
+    let mut rb: isize = 0;
+    let mut ip: usize = 0;
+    // hands the rest of the run to the interpreter, which also knows how to report faults
+    macro_rules! bail {
+        () => {
+            return interpret(m, ip, rb, input, output)
+        };
+    }
+    macro_rules! addr {
+        ($a:expr) => {
+            match address(&m, $a) {
+                Some(a) => a,
+                None => bail!(),
+            }
+        };
+    }
+    macro_rules! rd {
+        ($a:expr) => {{
+            let a = addr!($a);
+            read(&m, a)
+        }};
+    }
+    loop {
+        match ip {
+";
+    for (address, instruction) in instructions(program) {
+        out += &rust_arm(address, &instruction);
+    }
+    out += "            _ => bail!(),\n        }\n    }\n}\n";
+    out
+}
+
+fn rust_arm(address: usize, instruction: &Instruction) -> String {
+    let words = crate::format_opcodes(&instruction.encode()).replace(',', ", ");
+    let mut arm = format!(
+        "            // {}\n            {} => {{\n                if !unchanged(&m, {}, &[{}]) {{\n                    bail!()\n                }}\n",
+        instruction, address, address, words
+    );
+    let operands = instruction.operands();
+    let next = address + instruction.size();
+    let body: Vec<String> = match instruction.opcode {
+        Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => {
+            let (a, b) = (rust_read(&operands[0]), rust_read(&operands[1]));
+            let value = match instruction.opcode {
+                Opcode::Add => format!("isize::wrapping_add({}, {})", a, b),
+                Opcode::Mul => format!("isize::wrapping_mul({}, {})", a, b),
+                Opcode::Lt => format!("({} < {}) as isize", a, b),
+                _ => format!("({} == {}) as isize", a, b),
+            };
+            vec![
+                format!("let v = {};", value),
+                format!("let d = addr!({});", rust_address(&operands[2])),
+                "write(&mut m, d, v);".to_string(),
+                format!("ip = {};", next),
+            ]
+        }
+        Opcode::In => vec![
+            format!("let d = addr!({});", rust_address(&operands[0])),
+            "match input() {".to_string(),
+            "    Some(x) => write(&mut m, d, x),".to_string(),
+            "    None => {".to_string(),
+            "        return Err(Error {".to_string(),
+            format!("            ip: {},", address),
+            format!("            instruction: {},", instruction.encode()[0]),
+            "            kind: ErrorKind::InsufficientInput,".to_string(),
+            "        })".to_string(),
+            "    }".to_string(),
+            "}".to_string(),
+            format!("ip = {};", next),
+        ],
+        Opcode::Out => vec![
+            format!("let v = {};", rust_read(&operands[0])),
+            "output(v);".to_string(),
+            format!("ip = {};", next),
+        ],
+        Opcode::Jnz | Opcode::Jz => {
+            let test = if instruction.opcode == Opcode::Jnz {
+                "!="
+            } else {
+                "=="
+            };
+            // both operands are read before the test, as the interpreter does
+            let mut lines = vec![format!("let v = {};", rust_read(&operands[0]))];
+            let target = match operands[1] {
+                Operand {
+                    mode: Mode::Immediate,
+                    value,
+                } if value >= 0 => value.to_string(),
+                o => {
+                    lines.push(format!("let t = {};", rust_read(&o)));
+                    "match t {\n    t if t >= 0 => t as usize,\n    _ => bail!(),\n}".to_string()
+                }
+            };
+            lines.push(format!("if v {} 0 {{", test));
+            lines.extend(target.lines().enumerate().map(|(n, line)| {
+                if n == 0 {
+                    format!("    ip = {}", line)
+                } else {
+                    format!("    {}", line)
+                }
+            }));
+            let last = lines.len() - 1;
+            lines[last] += ";";
+            lines.push("} else {".to_string());
+            lines.push(format!("    ip = {};", next));
+            lines.push("}".to_string());
+            lines
+        }
+        Opcode::Arb => vec![
+            format!("let v = {};", rust_read(&operands[0])),
+            "rb = rb.wrapping_add(v);".to_string(),
+            format!("ip = {};", next),
+        ],
+        Opcode::Hlt => vec!["return Ok(m);".to_string()],
+    };
+    for line in body {
+        arm += &format!("                {}\n", line);
+    }
+    arm += "            }\n";
+    arm
+}
+
+fn rust_read(operand: &Operand) -> String {
+    match operand.mode {
+        Mode::Immediate => operand.value.to_string(),
+        _ => format!("rd!({})", rust_address(operand)),
+    }
+}
+
+fn rust_address(operand: &Operand) -> String {
+    match operand.mode {
+        Mode::Relative => format!("rb.wrapping_add({})", operand.value),
+        _ => operand.value.to_string(),
+    }
+}
+
+// The pieces of a run that the generated Rust relies on.
+
+// None for addresses the compiled code cannot handle itself: negative ones, or far past the end.
+pub fn address(memory: &[isize], address: isize) -> Option<usize> {
+    if address < 0 || address as usize > memory.len() + GROWTH_LIMIT {
+        None
+    } else {
+        Some(address as usize)
+    }
+}
+
+pub fn read(memory: &[isize], address: usize) -> isize {
+    memory.get(address).cloned().unwrap_or(0)
+}
+
+pub fn write(memory: &mut Vec<isize>, address: usize, value: isize) {
+    if address >= memory.len() {
+        memory.resize(address + 1, 0);
+    }
+    memory[address] = value;
+}
+
+pub fn unchanged(memory: &[isize], address: usize, words: &[isize]) -> bool {
+    memory.get(address..address + words.len()) == Some(words)
+}
+
+// Finishes a run in the interpreter, from wherever the compiled code gave up.
+pub fn interpret(
+    memory: Vec<isize>,
+    ip: usize,
+    relative_base: isize,
+    input: &mut dyn FnMut() -> Option<isize>,
+    output: &mut dyn FnMut(isize),
+) -> Result<Vec<isize>, Error> {
+    let mut machine = Machine::with_memory(AutoMemory::from(memory));
+    machine.set_ip(ip);
+    machine.set_relative_base(relative_base);
+    loop {
+        match machine.run_until_io()? {
+            State::NeedsInput => match input() {
+                Some(x) => machine.push_input(x),
+                None => return Err(machine.fault(ErrorKind::InsufficientInput)),
+            },
+            State::Output(x) => output(x),
+            State::Halted | State::Running => return machine.try_into_memory(),
+        }
+    }
+}
+
+const C_PRELUDE: &str = "\
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+enum {
+    INTCODE_HALTED = 0,
+    INTCODE_FAULT = 1,
+    INTCODE_NO_INPUT = 2,
+    INTCODE_SELF_MODIFYING = 3,
+    INTCODE_FAR_ADDRESS = 4,
+};
+
+/* Returns zero when there is no more input. */
+typedef int (*intcode_input)(void *context, int64_t *value);
+typedef void (*intcode_output)(void *context, int64_t value);
+
+struct memory {
+    int64_t *words;
+    size_t len;
+};
+
+static int64_t rd(const struct memory *m, size_t a) {
+    return a < m->len ? m->words[a] : 0;
+}
+
+/* Returns zero, with the status to stop with, for an address the compiled code cannot use. */
+static int address(const struct memory *m, int64_t a, size_t *out, int *status) {
+    if (a < 0) {
+        *status = INTCODE_FAULT;
+        return 0;
+    }
+    if ((uint64_t)a > m->len + GROWTH_LIMIT) {
+        *status = INTCODE_FAR_ADDRESS;
+        return 0;
+    }
+    *out = (size_t)a;
+    return 1;
+}
+
+static int wr(struct memory *m, size_t a, int64_t v) {
+    if (a >= m->len) {
+        size_t len = a + 1 > 2 * m->len ? a + 1 : 2 * m->len;
+        int64_t *words = realloc(m->words, len * sizeof(int64_t));
+        if (!words) {
+            return 0;
+        }
+        memset(words + m->len, 0, (len - m->len) * sizeof(int64_t));
+        m->words = words;
+        m->len = len;
+    }
+    m->words[a] = v;
+    return 1;
+}
+
+static int unchanged(const struct memory *m, size_t a, const int64_t *words, size_t n) {
+    size_t i;
+    for (i = 0; i < n; i++) {
+        if (rd(m, a + i) != words[i]) {
+            return 0;
+        }
+    }
+    return 1;
+}
+
+static int64_t wrapping_add(int64_t a, int64_t b) {
+    return (int64_t)((uint64_t)a + (uint64_t)b);
+}
+
+static int64_t wrapping_mul(int64_t a, int64_t b) {
+    return (int64_t)((uint64_t)a * (uint64_t)b);
+}
+";
+
+pub fn to_c(program: &[isize]) -> Result<String, SelfModifying> {
+    if let Some(found) = self_modifications(program).into_iter().next() {
+        return Err(found);
+    }
+
+    let instructions = instructions(program);
+    let labels: BTreeSet<usize> = instructions.iter().map(|&(a, _)| a).collect();
+
+    let mut out = "/* Generated by intcode-transpile; do not edit. */\n".to_string();
+    out += &C_PRELUDE.replace("GROWTH_LIMIT", &format!("{}u", GROWTH_LIMIT));
+    out += &format!(
+        "\nstatic const int64_t program[{}] = {{{}}};\n\n",
+        program.len().max(1),
+        crate::format_opcodes(program).replace(',', ", ")
+    );
+    out += "\
+/* Runs the program to completion.  Returns one of the INTCODE_ constants, and the address of the
+ * instruction it stopped at in *ip_out. */
+int intcode_run(intcode_input input, intcode_output output, void *context, size_t *ip_out) {
+    struct memory m;
+    int status;
+    size_t ip = 0, d;
+    int64_t a, b, t, rb = 0;
+
+    m.len = sizeof(program) / sizeof(program[0]);
+    m.words = malloc(m.len * sizeof(int64_t));
+    if (!m.words) {
+        return INTCODE_FAULT;
+    }
+    memcpy(m.words, program, sizeof(program));
+    (void)a;
+    (void)b;
+    (void)t;
+    (void)d;
+    (void)rb;
+    goto dispatch;
+
+";
+    for (address, instruction) in &instructions {
+        out += &c_label(*address, instruction, &labels);
+    }
+    out += "dispatch:\n    switch (ip) {\n";
+    for address in &labels {
+        out += &format!("    case {}:\n        goto L{};\n", address, address);
+    }
+    out += "\
+    default:
+        goto fault;
+    }
+fault:
+    status = INTCODE_FAULT;
+done:
+    free(m.words);
+    if (ip_out) {
+        *ip_out = ip;
+    }
+    return status;
+}
+";
+    Ok(out)
+}
+
+fn c_label(address: usize, instruction: &Instruction, labels: &BTreeSet<usize>) -> String {
+    let words = crate::format_opcodes(&instruction.encode()).replace(',', ", ");
+    let mut lines = vec![
+        format!("    /* {} */", instruction),
+        format!("L{}:", address),
+        format!("    ip = {};", address),
+        format!(
+            "    if (!unchanged(&m, {}, (const int64_t[]){{{}}}, {})) {{",
+            address,
+            words,
+            instruction.size()
+        ),
+        "        status = INTCODE_SELF_MODIFYING;".to_string(),
+        "        goto done;".to_string(),
+        "    }".to_string(),
+    ];
+    let goto = |target: usize| {
+        if labels.contains(&target) {
+            format!("goto L{};", target)
+        } else {
+            format!("ip = {};\n    goto dispatch;", target)
+        }
+    };
+
+    let operands = instruction.operands();
+    let next = address + instruction.size();
+    match instruction.opcode {
+        Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => {
+            lines.extend(c_read("a", &operands[0]));
+            lines.extend(c_read("b", &operands[1]));
+            lines.extend(c_address(&operands[2]));
+            let value = match instruction.opcode {
+                Opcode::Add => "wrapping_add(a, b)",
+                Opcode::Mul => "wrapping_mul(a, b)",
+                Opcode::Lt => "a < b",
+                _ => "a == b",
+            };
+            lines.push(format!("    if (!wr(&m, d, {})) goto fault;", value));
+            lines.push(format!("    {}", goto(next)));
+        }
+        Opcode::In => {
+            lines.extend(c_address(&operands[0]));
+            lines.push("    if (!input(context, &t)) {".to_string());
+            lines.push("        status = INTCODE_NO_INPUT;".to_string());
+            lines.push("        goto done;".to_string());
+            lines.push("    }".to_string());
+            lines.push("    if (!wr(&m, d, t)) goto fault;".to_string());
+            lines.push(format!("    {}", goto(next)));
+        }
+        Opcode::Out => {
+            lines.extend(c_read("a", &operands[0]));
+            lines.push("    output(context, a);".to_string());
+            lines.push(format!("    {}", goto(next)));
+        }
+        Opcode::Jnz | Opcode::Jz => {
+            let test = if instruction.opcode == Opcode::Jnz {
+                "!="
+            } else {
+                "=="
+            };
+            lines.extend(c_read("a", &operands[0]));
+            lines.extend(c_read("b", &operands[1]));
+            lines.push(format!("    if (a {} 0) {{", test));
+            match operands[1] {
+                Operand {
+                    mode: Mode::Immediate,
+                    value,
+                } if value >= 0 => lines.push(format!("        {}", goto(value as usize))),
+                _ => {
+                    lines.push("        if (b < 0) goto fault;".to_string());
+                    lines.push("        ip = (size_t)b;".to_string());
+                    lines.push("        goto dispatch;".to_string());
+                }
+            }
+            lines.push("    }".to_string());
+            lines.push(format!("    {}", goto(next)));
+        }
+        Opcode::Arb => {
+            lines.extend(c_read("a", &operands[0]));
+            lines.push("    rb = wrapping_add(rb, a);".to_string());
+            lines.push(format!("    {}", goto(next)));
+        }
+        Opcode::Hlt => {
+            lines.push("    status = INTCODE_HALTED;".to_string());
+            lines.push("    goto done;".to_string());
+        }
+    }
+
+    let mut label = lines.join("\n");
+    label += "\n";
+    label
+}
+
+fn c_read(variable: &str, operand: &Operand) -> Vec<String> {
+    match operand.mode {
+        Mode::Immediate => vec![format!("    {} = {};", variable, c_literal(operand.value))],
+        _ => {
+            let mut lines = c_address(operand);
+            lines.push(format!("    {} = rd(&m, d);", variable));
+            lines
+        }
+    }
+}
+
+fn c_address(operand: &Operand) -> Vec<String> {
+    let address = match operand.mode {
+        Mode::Relative => format!("wrapping_add(rb, {})", c_literal(operand.value)),
+        _ => c_literal(operand.value),
+    };
+    vec![format!(
+        "    if (!address(&m, {}, &d, &status)) goto done;",
+        address
+    )]
+}
+
+// INT64_MIN has no literal of its own in C.
+fn c_literal(value: isize) -> String {
+    if value == isize::MIN {
+        "INT64_MIN".to_string()
+    } else {
+        format!("INT64_C({})", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SelfModifying;
+
+    fn day(n: &str) -> Vec<isize> {
+        crate::parse_opcodes(&std::fs::read_to_string(format!("../inputs/day_{}.txt", n)).unwrap())
+    }
+
+    #[test]
+    fn detects_self_modification() {
+        let found = super::self_modifications(&day("05"));
+        assert_eq!(found[0], SelfModifying { ip: 2, address: 6 });
+        assert!(super::to_c(&day("05")).is_err());
+
+        assert_eq!(super::self_modifications(&day("09")), vec![]);
+        assert!(super::to_c(&day("09")).is_ok());
+    }
+
+    #[test]
+    fn jump_reads_target_first() {
+        // jz [7], [-1]: the target is read, and faults, even though the jump is not taken
+        let rust = super::to_rust(&[6, 7, -1, 104, 5, 99, 0, 1]);
+        assert!(rust.contains(
+            "
+                let v = rd!(7);
+                let t = rd!(-1);
+                if v == 0 {
+                    ip = match t {
+                        t if t >= 0 => t as usize,
+                        _ => bail!(),
+                    };
+                } else {
+                    ip = 3;
+                }
+"
+        ));
+    }
+
+    #[test]
+    fn rust_arms() {
+        let rust = super::to_rust(&[1101, 2, 3, 5, 99]);
+        assert!(rust.contains("pub const PROGRAM: [isize; 5] = [1101, 2, 3, 5, 99];\n"));
+        assert!(rust.contains(
+            "
+            // add #2, #3, [5]
+            0 => {
+                if !unchanged(&m, 0, &[1101, 2, 3, 5]) {
+                    bail!()
+                }
+                let v = isize::wrapping_add(2, 3);
+                let d = addr!(5);
+                write(&mut m, d, v);
+                ip = 4;
+            }
+"
+        ));
+    }
+}
@@ -0,0 +1,138 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+
+// The type of a single memory cell.  The interpreter is generic over this so that programs which
+// need values bigger than 64 bits can run on i128 or BigInt.
+pub trait Word:
+    Clone + Default + fmt::Debug + fmt::Display + FromStr + Eq + Ord + Hash + 'static
+{
+    fn from_isize(x: isize) -> Self;
+    fn to_isize(&self) -> Option<isize>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+
+    // The low five decimal digits (with the sign of self), which is all that the opcode and
+    // parameter modes of an instruction are made of.
+    fn low_digits(&self) -> isize;
+
+    fn to_usize(&self) -> Option<usize> {
+        self.to_isize().and_then(|x| x.try_into().ok())
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+macro_rules! primitive_word {
+    ($($t:ty)*) => {
+        $(
+            impl Word for $t {
+                fn from_isize(x: isize) -> $t {
+                    x as $t
+                }
+
+                fn to_isize(&self) -> Option<isize> {
+                    (*self).try_into().ok()
+                }
+
+                fn checked_add(&self, other: &$t) -> Option<$t> {
+                    <$t>::checked_add(*self, *other)
+                }
+
+                fn checked_mul(&self, other: &$t) -> Option<$t> {
+                    <$t>::checked_mul(*self, *other)
+                }
+
+                fn wrapping_add(&self, other: &$t) -> $t {
+                    <$t>::wrapping_add(*self, *other)
+                }
+
+                fn wrapping_mul(&self, other: &$t) -> $t {
+                    <$t>::wrapping_mul(*self, *other)
+                }
+
+                fn low_digits(&self) -> isize {
+                    (*self % 100_000) as isize
+                }
+            }
+        )*
+    };
+}
+
+primitive_word!(isize i64 i128);
+
+// Never overflows, so wrapping and checked arithmetic are the same thing.
+impl Word for BigInt {
+    fn from_isize(x: isize) -> BigInt {
+        BigInt::from(x)
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        self.try_into().ok()
+    }
+
+    fn checked_add(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &BigInt) -> BigInt {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &BigInt) -> BigInt {
+        self * other
+    }
+
+    fn low_digits(&self) -> isize {
+        (self % BigInt::from(100_000))
+            .try_into()
+            .expect("a remainder always fits")
+    }
+}
+
+// What the arithmetic instructions (and relative-base adjustments) do when the result does not
+// fit in a Word.  Checked arithmetic faults with ErrorKind::Overflow at the offending instruction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    #[default]
+    Wrapping,
+    Checked,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Word;
+    use num_bigint::BigInt;
+
+    #[test]
+    fn low_digits() {
+        assert_eq!(21101isize.low_digits(), 21101);
+        assert_eq!(1_234_521_101i64.low_digits(), 21101);
+        assert_eq!((-7i128).low_digits(), -7);
+        let big: BigInt = "123456789012345678901234567890121101".parse().unwrap();
+        assert_eq!(big.low_digits(), 21101);
+        assert_eq!(big.to_isize(), None);
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(Word::checked_mul(&i64::MAX, &2), None);
+        assert_eq!(Word::wrapping_add(&i64::MAX, &1), i64::MIN);
+        assert_eq!(
+            Word::checked_mul(&BigInt::from(i128::MAX), &BigInt::from(2)),
+            Some(BigInt::from(i128::MAX) * 2)
+        );
+    }
+}
@@ -0,0 +1,35 @@
+// Differential fuzzing of the backends against Machine; see intcode::fuzz.
+//
+// Any program that makes a backend disagree is minimized and saved under fuzz/, where it stays as
+// a regression test once the bug is fixed.
+
+use intcode::fuzz::{self, Case, BACKENDS};
+
+#[test]
+fn regressions() {
+    for entry in std::fs::read_dir("tests/fuzz").unwrap() {
+        let path = entry.unwrap().path();
+        let case = Case::from_text(&std::fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|| panic!("{} is not a test case", path.display()));
+        if let Err(mismatch) = fuzz::check(&case, BACKENDS) {
+            panic!("{}: {}", path.display(), mismatch);
+        }
+    }
+}
+
+#[test]
+fn random_programs() {
+    for seed in 0..500 {
+        let case = fuzz::generate(seed);
+        if fuzz::check(&case, BACKENDS).is_err() {
+            let case = fuzz::minimize(case, BACKENDS);
+            let path = fuzz::save(&case, "tests/fuzz").unwrap();
+            panic!(
+                "seed {}: {}\nsaved as {}",
+                seed,
+                fuzz::check(&case, BACKENDS).unwrap_err(),
+                path.display()
+            );
+        }
+    }
+}
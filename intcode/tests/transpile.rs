@@ -0,0 +1,191 @@
+// Runs programs compiled by intcode-transpile and checks that they behave like the interpreter.
+//
+// The generated Rust is built as a crate of its own, depending on this one, much as the generated
+// C is handed to the C compiler.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn program(day: &str) -> Vec<isize> {
+    intcode::parse_opcodes(&std::fs::read_to_string(format!("../inputs/day_{}.txt", day)).unwrap())
+}
+
+// jz [7], [-1], which faults on reading its target even though the jump is not taken
+const JZ_FAULT: [isize; 8] = [6, 7, -1, 104, 5, 99, 0, 1];
+
+// The programs built into the Rust binary, by module name.
+fn programs() -> Vec<(&'static str, Vec<isize>)> {
+    vec![
+        ("day_05", program("05")),
+        ("day_09", program("09")),
+        ("jz_fault", JZ_FAULT.to_vec()),
+    ]
+}
+
+const RUST_DRIVER: &str = r#"
+use std::collections::VecDeque;
+
+mod day_05;
+mod day_09;
+mod jz_fault;
+
+// Usage: transpiled PROGRAM INPUT...  Prints the result as the interpreter's try_run_with_io would.
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let run = match args[1].as_str() {
+        "day_05" => day_05::run,
+        "day_09" => day_09::run,
+        "jz_fault" => jz_fault::run,
+        name => panic!("no program {}", name),
+    };
+    let mut input: VecDeque<isize> = args[2..].iter().map(|x| x.parse().unwrap()).collect();
+    let mut output = Vec::new();
+    let memory = run(&mut || input.pop_front(), &mut |x| output.push(x));
+    print!("{:?}", memory.map(|m| (m, output)));
+}
+"#;
+
+// Builds programs() into one binary.  The crate is kept between runs, so that cargo only
+// rebuilds what changed.
+fn build_rust() -> PathBuf {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("transpiled");
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\n\
+             name = \"transpiled\"\n\
+             version = \"0.1.0\"\n\
+             edition = \"2018\"\n\n\
+             [dependencies]\n\
+             intcode = {{ path = {:?} }}\n\n\
+             [workspace]\n",
+            manifest_dir
+        ),
+    )
+    .unwrap();
+    // the same versions of intcode's dependencies as the workspace, which are already downloaded
+    let lock = manifest_dir.join("../Cargo.lock");
+    if lock.exists() && !dir.join("Cargo.lock").exists() {
+        std::fs::copy(lock, dir.join("Cargo.lock")).unwrap();
+    }
+    std::fs::write(dir.join("src/main.rs"), RUST_DRIVER).unwrap();
+    for (name, program) in programs() {
+        let source = intcode::transpile::to_rust(&program);
+        std::fs::write(dir.join(format!("src/{}.rs", name)), source).unwrap();
+    }
+
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--quiet", "--offline", "--manifest-path"])
+        .arg(dir.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    dir.join("target/debug")
+        .join(format!("transpiled{}", std::env::consts::EXE_SUFFIX))
+}
+
+#[test]
+fn rust_matches_interpreter() {
+    let binary = build_rust();
+    let programs = programs();
+    // day 5 rewrites its own code straight away, so almost all of it runs in the interpreter
+    for (name, input) in &[
+        ("day_05", vec![1]),
+        ("day_05", vec![5]),
+        ("day_05", vec![]),
+        ("day_09", vec![1]),
+        ("day_09", vec![2]),
+        ("day_09", vec![]),
+        ("jz_fault", vec![]),
+    ] {
+        let result = Command::new(&binary)
+            .arg(name)
+            .args(input.iter().map(|x| x.to_string()))
+            .output()
+            .unwrap();
+        assert!(result.status.success());
+        let program = &programs.iter().find(|(n, _)| n == name).unwrap().1;
+        let expected = intcode::try_run_with_io(program.clone(), input.clone().into());
+        assert_eq!(
+            String::from_utf8(result.stdout).unwrap(),
+            format!("{:?}", expected)
+        );
+    }
+}
+
+const C_DRIVER: &str = r#"
+#include <inttypes.h>
+#include <stdio.h>
+
+static int next_input(void *context, int64_t *value) {
+    return scanf("%" SCNd64, value) == 1;
+}
+
+static void print_output(void *context, int64_t value) {
+    printf("%" PRId64 "\n", value);
+}
+
+int main(void) {
+    return intcode_run(next_input, print_output, NULL, NULL);
+}
+"#;
+
+// Compiles `program` with the C driver, into `dir`.
+fn build_c(dir: &Path, name: &str, program: &[isize]) -> PathBuf {
+    let source = dir.join(format!("{}.c", name));
+    let binary = dir.join(name);
+    let c = intcode::transpile::to_c(program).unwrap();
+    std::fs::write(&source, c + C_DRIVER).unwrap();
+    let status = Command::new("cc")
+        .args(["-O1", "-o"])
+        .arg(&binary)
+        .arg(&source)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    binary
+}
+
+#[test]
+fn c_matches_interpreter() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("no C compiler; skipping");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("intcode-transpile-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let binary = build_c(&dir, "day_09", &program("09"));
+
+    for input in &[1, 2] {
+        let mut child = Command::new(&binary)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        use std::io::Write;
+        writeln!(child.stdin.take().unwrap(), "{}", input).unwrap();
+        let result = child.wait_with_output().unwrap();
+        assert_eq!(result.status.code(), Some(0));
+
+        let output: Vec<isize> = String::from_utf8(result.stdout)
+            .unwrap()
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let (_, expected) = intcode::run_with_io(program("09"), vec![*input].into());
+        assert_eq!(output, expected);
+    }
+
+    // a write the interpreter accepts but the C code will not grow memory for
+    let far = vec![1101, 1, 2, 2_000_000, 104, 7, 99];
+    assert_eq!(intcode::run_with_io(far.clone(), vec![].into()).1, vec![7]);
+    let result = Command::new(build_c(&dir, "far", &far)).output().unwrap();
+    // INTCODE_FAR_ADDRESS
+    assert_eq!(result.status.code(), Some(4));
+    assert!(result.stdout.is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}